@@ -0,0 +1,191 @@
+#![no_std]
+
+use soroban_sdk::{
+    contract, contractclient, contracterror, contractimpl, contracttype, panic_with_error, symbol_short, token::TokenInterface, Address, Env, String
+};
+
+pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
+pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+pub(crate) const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub enum DataKey {
+    LendingContract,
+    LoanKey,
+    Allowance(Address, Address)
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    InvalidAmount = 3,
+    InsufficientBalance = 4,
+    InsufficientAllowance = 5,
+    InvalidExpiration = 6,
+    NotSupported = 7
+}
+
+// part of the P2P lending contract interface the position token relies on
+#[contractclient(name = "LendingClient")]
+pub trait Lending {
+    fn get_lender(env: Env, loan_key: u64) -> Address;
+    fn transfer_position_by_token(env: Env, loan_key: u64, from: Address, to: Address);
+}
+
+// Token representing the lender position of a single loan, the lender holds
+// the only unit and transferring it moves the position in the lending contract
+#[contract]
+pub struct PositionToken;
+
+#[contractimpl]
+impl PositionToken {
+    // called by the lending contract right after deploying the token
+    pub fn initialize(env: Env, lending_contract: Address, loan_key: u64) {
+        if env.storage().instance().has(&DataKey::LendingContract) {
+            panic_with_error!(&env, Error::AlreadyInitialized);
+        }
+
+        lending_contract.require_auth();
+
+        env.storage().instance().set(&DataKey::LendingContract, &lending_contract);
+        env.storage().instance().set(&DataKey::LoanKey, &loan_key);
+        env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    pub fn lending_contract(env: Env) -> Address {
+        env.storage().instance().get(&DataKey::LendingContract).unwrap_or_else(|| panic_with_error!(&env, Error::NotInitialized))
+    }
+
+    pub fn loan_key(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::LoanKey).unwrap_or_else(|| panic_with_error!(&env, Error::NotInitialized))
+    }
+}
+
+#[contractimpl]
+impl TokenInterface for PositionToken {
+    fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+        _allowance(&env, &from, &spender)
+    }
+
+    fn approve(env: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        from.require_auth();
+
+        if amount < 0 {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        if amount > 0 && expiration_ledger < env.ledger().sequence() {
+            panic_with_error!(&env, Error::InvalidExpiration);
+        }
+
+        let key = DataKey::Allowance(from.clone(), spender.clone());
+        env.storage().temporary().set(&key, &AllowanceValue { amount, expiration_ledger });
+
+        if amount > 0 {
+            let live_for = expiration_ledger - env.ledger().sequence();
+            env.storage().temporary().extend_ttl(&key, live_for, live_for);
+        }
+
+        env.events().publish((symbol_short!("approve"), from, spender), (amount, expiration_ledger));
+    }
+
+    fn balance(env: Env, id: Address) -> i128 {
+        let lending_contract = LendingClient::new(&env, &Self::lending_contract(env.clone()));
+
+        // closed loans have no holder
+        match lending_contract.try_get_lender(&Self::loan_key(env.clone())) {
+            Ok(Ok(lender)) if lender == id => 1,
+            _ => 0
+        }
+    }
+
+    fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+
+        _transfer(&env, &from, &to, amount);
+    }
+
+    fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+
+        let allowance = _allowance(&env, &from, &spender);
+        if allowance < amount {
+            panic_with_error!(&env, Error::InsufficientAllowance);
+        }
+
+        let key = DataKey::Allowance(from.clone(), spender);
+        let mut allowance_value: AllowanceValue = env.storage().temporary().get(&key).unwrap();
+        allowance_value.amount -= amount;
+        env.storage().temporary().set(&key, &allowance_value);
+
+        _transfer(&env, &from, &to, amount);
+    }
+
+    // the position can only be closed through the lending contract
+    fn burn(env: Env, _from: Address, _amount: i128) {
+        panic_with_error!(&env, Error::NotSupported);
+    }
+
+    fn burn_from(env: Env, _spender: Address, _from: Address, _amount: i128) {
+        panic_with_error!(&env, Error::NotSupported);
+    }
+
+    fn decimals(_env: Env) -> u32 {
+        0
+    }
+
+    fn name(env: Env) -> String {
+        String::from_str(&env, "P2P Lending Position")
+    }
+
+    fn symbol(env: Env) -> String {
+        String::from_str(&env, "LOANPOS")
+    }
+}
+
+fn _allowance(
+    env: &Env,
+    from: &Address,
+    spender: &Address
+) -> i128 {
+    let allowance_value: Option<AllowanceValue> = env.storage().temporary().get(&DataKey::Allowance(from.clone(), spender.clone()));
+
+    match allowance_value {
+        Some(allowance_value) if allowance_value.expiration_ledger >= env.ledger().sequence() => allowance_value.amount,
+        _ => 0
+    }
+}
+
+fn _transfer(
+    env: &Env,
+    from: &Address,
+    to: &Address,
+    amount: i128
+) {
+    // the position is indivisible
+    if amount != 1 {
+        panic_with_error!(env, Error::InvalidAmount);
+    }
+
+    if PositionToken::balance(env.clone(), from.clone()) != 1 {
+        panic_with_error!(env, Error::InsufficientBalance);
+    }
+
+    let lending_contract = LendingClient::new(env, &PositionToken::lending_contract(env.clone()));
+    lending_contract.transfer_position_by_token(&PositionToken::loan_key(env.clone()), from, to);
+
+    env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+
+    env.events().publish((symbol_short!("transfer"), from.clone(), to.clone()), amount);
+}
@@ -0,0 +1,104 @@
+#![no_std]
+
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Env, Symbol, Vec};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub enum DataKey {
+    Decimals,
+    Resolution,
+    Assets,
+    Prices(Asset)
+}
+
+pub const DEFAULT_DECIMALS: u32 = 14;
+pub const DEFAULT_RESOLUTION: u32 = 300;
+
+// Mock implementing the read interface of the Reflector price feed,
+// prices are pushed by the test instead of the oracle nodes
+#[contract]
+pub struct ReflectorMock;
+
+#[contractimpl]
+impl ReflectorMock {
+    // set price decimals
+    pub fn set_decimals(env: Env, decimals: u32) {
+        env.storage().instance().set(&DataKey::Decimals, &decimals);
+    }
+
+    // set sampling interval in seconds
+    pub fn set_resolution(env: Env, resolution: u32) {
+        env.storage().instance().set(&DataKey::Resolution, &resolution);
+    }
+
+    // record a new price for the asset
+    pub fn set_price(env: Env, asset: Asset, price: i128, timestamp: u64) {
+        let mut assets: Vec<Asset> = env.storage().instance().get(&DataKey::Assets).unwrap_or(Vec::new(&env));
+        if assets.first_index_of(&asset).is_none() {
+            assets.push_back(asset.clone());
+            env.storage().instance().set(&DataKey::Assets, &assets);
+        }
+
+        // most recent record first
+        let mut prices: Vec<PriceData> = env.storage().instance().get(&DataKey::Prices(asset.clone())).unwrap_or(Vec::new(&env));
+        prices.push_front(PriceData { price, timestamp });
+
+        env.storage().instance().set(&DataKey::Prices(asset), &prices);
+    }
+
+    // drop all recorded prices of the asset
+    pub fn clear_prices(env: Env, asset: Asset) {
+        env.storage().instance().remove(&DataKey::Prices(asset));
+    }
+
+    pub fn decimals(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::Decimals).unwrap_or(DEFAULT_DECIMALS)
+    }
+
+    pub fn resolution(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::Resolution).unwrap_or(DEFAULT_RESOLUTION)
+    }
+
+    pub fn assets(env: Env) -> Vec<Asset> {
+        env.storage().instance().get(&DataKey::Assets).unwrap_or(Vec::new(&env))
+    }
+
+    pub fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+        let prices: Vec<PriceData> = env.storage().instance().get(&DataKey::Prices(asset))?;
+
+        prices.first()
+    }
+
+    pub fn prices(env: Env, asset: Asset, records: u32) -> Option<Vec<PriceData>> {
+        let prices: Vec<PriceData> = env.storage().instance().get(&DataKey::Prices(asset))?;
+
+        if prices.is_empty() || records == 0 {
+            return None;
+        }
+
+        Some(prices.slice(0..records.min(prices.len())))
+    }
+
+    // average of the last `records` prices
+    pub fn twap(env: Env, asset: Asset, records: u32) -> Option<i128> {
+        let prices = Self::prices(env, asset, records)?;
+
+        let sum: i128 = prices.iter().map(|price_data| price_data.price).sum();
+
+        Some(sum / prices.len() as i128)
+    }
+}
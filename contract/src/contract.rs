@@ -1,12 +1,15 @@
 use soroban_sdk::{
-    contract, contractimpl, Address, Env, Vec, Map, token, panic_with_error
+    contract, contractimpl, Address, BytesN, Env, Vec, Map, token, panic_with_error, I256
 };
 use crate::types::*;
 use crate::event;
+use crate::position_token_contract;
 
 pub trait P2PLendingTrait {
     // create new loan
     fn new_loan(env: Env, loan_key: u64, loan: Loan);
+    // create new loan with a contract-assigned key
+    fn create_loan(env: Env, loan: Loan) -> u64;
     // cancel loan
     fn cancel_loan(env: Env, loan_key: u64);
     // lend
@@ -16,8 +19,40 @@ pub trait P2PLendingTrait {
 
     // repay loan
     fn repay(env: Env, loan_key: u64, user: Address);
-    // seize collateral
+    // repay part of the loan
+    fn repay_partial(env: Env, loan_key: u64, user: Address, amount: i128);
+    // seize collateral, or take it over after an auction ended without a bid
     fn seize(env: Env, loan_key: u64);
+    // liquidate part of a collateral asset at oracle prices to cover part of the debt,
+    // liquidators other than the lender pay the covered debt to the lender
+    fn liquidate(env: Env, loan_key: u64, liquidator: Address, asset_contract: Address, amount: i128);
+    // accrue interest at the current rate and move a floating rate to its reference
+    fn update_interest_rate(env: Env, loan_key: u64);
+    // buy the auctioned collateral at the current price
+    fn bid(env: Env, loan_key: u64, bidder: Address);
+    // add collateral to an in progress loan
+    fn add_collateral(env: Env, loan_key: u64, asset_contract: Address, amount: i128);
+    // set min collateral ratio kept on collateral withdrawals
+    fn set_withdrawal_limit(env: Env, loan_key: u64, collateral_assets: Vec<OracleAsset>, loan_asset: OracleAsset, min_ratio: u32);
+    // withdraw collateral above the withdrawal limit
+    fn withdraw_collateral(env: Env, loan_key: u64, asset_contract: Address, amount: i128);
+
+    // pay off the current lender and take over the loan with new terms
+    fn refinance(env: Env, loan_key: u64, lender: Address, max_loan_term: u32, daily_interest_rate: u32);
+
+    // assign the lender position to another address, optionally for a price
+    fn transfer_position(env: Env, loan_key: u64, lender: Address, new_lender: Address, price_asset: Option<Address>, price: i128);
+    // deploy a position token for the lender position and link it to the loan
+    fn tokenize_position(env: Env, loan_key: u64, lender: Address) -> Address;
+    // move the lender position on a transfer of the position token
+    fn transfer_position_by_token(env: Env, loan_key: u64, from: Address, to: Address);
+
+    // propose new loan terms to the other party
+    fn propose_amendment(env: Env, loan_key: u64, user: Address, max_loan_term: u32, daily_interest_rate: u32, fee: i128);
+    // accept loan terms proposed by the other party
+    fn accept_amendment(env: Env, loan_key: u64, user: Address);
+    // cancel or reject proposed loan terms
+    fn cancel_amendment(env: Env, loan_key: u64, user: Address);
 
     // get loan
     fn get_loan(env: Env, loan_key: u64) -> Loan;
@@ -25,6 +60,22 @@ pub trait P2PLendingTrait {
     fn get_loans(env: Env, user: Address) -> Vec<u64>;
     // get current interest
     fn get_interest(env: Env, loan_key: u64) -> i128;
+    // get current state of each seize condition
+    fn get_seize_status(env: Env, loan_key: u64) -> Vec<CollateralSeizeStatus>;
+    // get proposed loan terms
+    fn get_amendment(env: Env, loan_key: u64) -> LoanAmendment;
+    // get current lender of an in progress loan
+    fn get_lender(env: Env, loan_key: u64) -> Address;
+    // get position token of the loan
+    fn get_position_token(env: Env, loan_key: u64) -> Address;
+    // get collateral withdrawal limit of the loan
+    fn get_withdrawal_limit(env: Env, loan_key: u64) -> WithdrawalLimit;
+    // get collateral auction of the loan
+    fn get_auction(env: Env, loan_key: u64) -> Auction;
+    // get current price of the collateral auction
+    fn get_auction_price(env: Env, loan_key: u64) -> i128;
+    // get paid and missed installments and the next payment
+    fn get_installments(env: Env, loan_key: u64) -> InstallmentStatus;
 }
 
 
@@ -42,59 +93,27 @@ pub struct P2PLendingContract;
 #[contractimpl]
 impl P2PLendingTrait for P2PLendingContract {
     fn new_loan(env: Env, loan_key: u64, loan: Loan) {
-        if env.storage().persistent().has(&DataKey::Loan(loan_key.clone())) {
-            panic_with_error!(&env, Error::LoanAlreadyExist);
+        if loan_key >= ASSIGNED_LOAN_KEY_START {
+            panic_with_error!(&env, Error::InvalidLoanKey);
         }
 
-        if loan.collateral.is_some() {
-            let collateral = loan.collateral.clone().unwrap();
-
-            if collateral.seize_conditions.len() == 0 {
-                panic_with_error!(&env, Error::InvalidCollateral);
-            }
+        if env.storage().persistent().has(&DataKey::Loan(loan_key)) {
+            panic_with_error!(&env, Error::LoanAlreadyExist);
         }
 
-        match loan.status { 
-            LoanStatus::WaitingForLender => {
-                let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
-                borrower.require_auth();
-
-                // transfering collateral to the smart contract
-                if loan.collateral.is_some() {
-                    let collateral = loan.collateral.clone().unwrap();
-                    _transfer_tokens(&env, &collateral.asset_contract, &borrower, &env.current_contract_address(), collateral.amount);
-                }
-
-                _modify_loan_list(&env, &borrower, loan_key, true);
-            },
-            LoanStatus::WaitingForBorrower => {
-                let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
-                lender.require_auth();
-
-                // transfering loan amount to the smart contract
-                _transfer_tokens(&env, &loan.loan_asset, &lender, &env.current_contract_address(), loan.loan_amount);
-
-                _modify_loan_list(&env, &lender, loan_key, true);
-            },
-            LoanStatus::InProgress => {
-                // can't create a loan with in progress status
-                panic_with_error!(&env, Error::LoanInProgress);
-            }
-        }
+        _new_loan(&env, loan_key, loan);
+    }
 
-        env.storage().persistent().set(&DataKey::Loan(loan_key.clone()), &loan);
+    fn create_loan(env: Env, loan: Loan) -> u64 {
+        let loan_key = _next_loan_key(&env);
 
-        env.storage().persistent().extend_ttl(
-            &DataKey::Loan(loan_key.clone()),
-            PERSISTENT_LIFETIME_THRESHOLD,
-            PERSISTENT_BUMP_AMOUNT
-        );
+        _new_loan(&env, loan_key, loan);
 
-        event::new_loan(&env, loan_key.clone());
+        loan_key
     }
 
     fn cancel_loan(env: Env, loan_key: u64) {
-        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key.clone())).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
         match loan.status { 
             LoanStatus::WaitingForLender => {
@@ -102,8 +121,7 @@ impl P2PLendingTrait for P2PLendingContract {
                 borrower.require_auth();
 
                 // returning collateral to borrower
-                if loan.collateral.is_some() {
-                    let collateral = loan.collateral.unwrap();
+                for collateral in loan.collateral.iter() {
                     _transfer_tokens(&env, &collateral.asset_contract, &env.current_contract_address(), &borrower, collateral.amount);
                 }
 
@@ -118,19 +136,19 @@ impl P2PLendingTrait for P2PLendingContract {
 
                 _modify_loan_list(&env, &lender, loan_key, false);
             },
-            LoanStatus::InProgress => {
+            LoanStatus::InProgress | LoanStatus::Auction => {
                 // In progress loans can't be canceled
                 panic_with_error!(&env, Error::LoanInProgress);
             }
         }
 
-        env.storage().persistent().remove(&DataKey::Loan(loan_key.clone())); 
+        env.storage().persistent().remove(&DataKey::Loan(loan_key)); 
 
-        event::loan_canceled(&env, loan_key.clone());
+        event::loan_canceled(&env, loan_key);
     }
 
     fn lend(env: Env, loan_key: u64, lender: Address) {
-        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key.clone())).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
         lender.require_auth();
 
@@ -139,10 +157,8 @@ impl P2PLendingTrait for P2PLendingContract {
         }
 
         // checking if the loan reserved for specific lender
-        if loan.lender.is_some() {
-            if lender != loan.lender.clone().unwrap() {
-                panic_with_error!(&env, Error::InvalidLender);
-            }
+        if loan.lender.is_some() && lender != loan.lender.clone().unwrap() {
+            panic_with_error!(&env, Error::InvalidLender);
         }
 
         let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
@@ -158,23 +174,28 @@ impl P2PLendingTrait for P2PLendingContract {
         loan.lender = Some(lender.clone());
         let ledger = env.ledger();
         loan.timestamp = ledger.timestamp();
+        loan.interest_timestamp = loan.timestamp;
+        loan.accrued_interest = 0;
+        loan.uncompounded_interest = 0;
         loan.status = LoanStatus::InProgress;
+        _checkpoint_floating_rate(&env, &mut loan);
+        _start_installments(&mut loan);
 
-        env.storage().persistent().set(&DataKey::Loan(loan_key.clone()), &loan);
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
 
         env.storage().persistent().extend_ttl(
-            &DataKey::Loan(loan_key.clone()),
+            &DataKey::Loan(loan_key),
             PERSISTENT_LIFETIME_THRESHOLD,
             PERSISTENT_BUMP_AMOUNT
         );
 
         _modify_loan_list(&env, &lender, loan_key, true);
 
-        event::new_loan(&env, loan_key.clone());
+        event::new_loan(&env, loan_key);
     }
 
     fn borrow(env: Env, loan_key: u64, borrower: Address) {
-        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key.clone())).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
         borrower.require_auth();
 
@@ -183,10 +204,8 @@ impl P2PLendingTrait for P2PLendingContract {
         }
 
         // checking if the loan reserved for specific borrower
-        if loan.borrower.is_some() {
-            if borrower != loan.borrower.clone().unwrap() {
-                panic_with_error!(&env, Error::InvalidBorrower);
-            }
+        if loan.borrower.is_some() && borrower != loan.borrower.clone().unwrap() {
+            panic_with_error!(&env, Error::InvalidBorrower);
         }
 
         if loan.lender.clone().unwrap() == borrower {
@@ -197,8 +216,7 @@ impl P2PLendingTrait for P2PLendingContract {
         _transfer_tokens(&env, &loan.loan_asset, &env.current_contract_address(), &borrower, loan.loan_amount);
 
         // transfering collateral from borrower to smart contract
-        if loan.collateral.is_some() {
-            let collateral = loan.collateral.clone().unwrap();
+        for collateral in loan.collateral.iter() {
             _transfer_tokens(&env, &collateral.asset_contract, &borrower, &env.current_contract_address(), collateral.amount); 
         }
         
@@ -207,23 +225,28 @@ impl P2PLendingTrait for P2PLendingContract {
         loan.borrower = Some(borrower.clone());
         let ledger = env.ledger();
         loan.timestamp = ledger.timestamp();
+        loan.interest_timestamp = loan.timestamp;
+        loan.accrued_interest = 0;
+        loan.uncompounded_interest = 0;
         loan.status = LoanStatus::InProgress;
+        _checkpoint_floating_rate(&env, &mut loan);
+        _start_installments(&mut loan);
 
-        env.storage().persistent().set(&DataKey::Loan(loan_key.clone()), &loan);
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
 
         env.storage().persistent().extend_ttl(
-            &DataKey::Loan(loan_key.clone()),
+            &DataKey::Loan(loan_key),
             PERSISTENT_LIFETIME_THRESHOLD,
             PERSISTENT_BUMP_AMOUNT
         );
 
         _modify_loan_list(&env, &borrower, loan_key, true);
 
-        event::new_loan(&env, loan_key.clone());
+        event::new_loan(&env, loan_key);
     }
 
     fn repay(env: Env, loan_key: u64, user: Address) {
-        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key.clone())).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
         user.require_auth();
 
@@ -238,139 +261,1373 @@ impl P2PLendingTrait for P2PLendingContract {
         let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
         _create_claimable_balance(&env, &loan.loan_asset, &user, &lender, total_amount);
 
-        // returning collateral from smart contract to borrower
-        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
-        if loan.collateral.is_some() {
-            let collateral = loan.collateral.clone().unwrap();
-            _create_claimable_balance(&env, &collateral.asset_contract, &env.current_contract_address(), &borrower, collateral.amount);   
+        _close_repaid_loan(&env, loan_key, &loan);
+    }
+
+    fn repay_partial(env: Env, loan_key: u64, user: Address, amount: i128) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        user.require_auth();
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        if amount <= 0 {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        _accrue_interest(&env, &mut loan);
+
+        // overpayments are capped at the outstanding balance
+        let outstanding = loan.loan_amount + loan.accrued_interest;
+        let payment = amount.min(outstanding);
+
+        // transfering the payment from user to lender
+        let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+        _create_claimable_balance(&env, &loan.loan_asset, &user, &lender, payment);
+
+        _apply_payment(&mut loan, payment);
+
+        if loan.loan_amount == 0 && loan.accrued_interest == 0 {
+            _close_repaid_loan(&env, loan_key, &loan);
+            return;
         }
 
-        _modify_loan_list(&env, &lender, loan_key, false);
-        _modify_loan_list(&env, &borrower, loan_key, false);
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
 
-        env.storage().persistent().remove(&DataKey::Loan(loan_key.clone())); 
+        env.storage().persistent().extend_ttl(
+            &DataKey::Loan(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
 
-        event::loan_repaid(&env, loan_key.clone());
+        event::loan_partially_repaid(&env, loan_key, payment);
     }
 
     fn seize(env: Env, loan_key: u64) {
-        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key.clone())).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status == LoanStatus::Auction {
+            _close_auction(&env, loan_key, &loan);
+            return;
+        }
 
         if loan.status != LoanStatus::InProgress {
             panic_with_error!(&env, Error::LoanNotInProgress);
         }
 
-        if loan.collateral.is_none() {
+        if loan.collateral.is_empty() {
             panic_with_error!(&env, Error::InvalidCollateral);
         }
 
-        let collateral = loan.collateral.clone().unwrap();
-
         let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
 
-        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
-
         // only lender can seize collateral
         lender.require_auth();
 
         // checking if the collateral can be seized
-        if !collateral.seizable(&env, loan) {
+        if !loan.seizable(&env) {
             panic_with_error!(&env, Error::CollateralNotSeizable);
         }
 
-        // transfering collateral from smart contract to lender
-        _create_claimable_balance(&env, &collateral.asset_contract, &env.current_contract_address(), &lender, collateral.amount);
+        if let Liquidation::Auction(terms) = loan.liquidation.clone() {
+            _start_auction(&env, loan_key, &mut loan, &terms);
+            return;
+        }
+
+        let terms = match loan.liquidation.clone() {
+            Liquidation::Oracle(terms) if terms.return_surplus => terms,
+            _ => {
+                // transfering all collateral assets from smart contract to lender
+                for collateral in loan.collateral.iter() {
+                    _create_claimable_balance(&env, &collateral.asset_contract, &env.current_contract_address(), &lender, collateral.amount);
+                }
 
-        _modify_loan_list(&env, &lender, loan_key, false);
-        _modify_loan_list(&env, &borrower, loan_key, false);
+                _remove_loan(&env, loan_key, &loan);
 
-        env.storage().persistent().remove(&DataKey::Loan(loan_key.clone())); 
+                event::collateral_seized(&env, loan_key);
+                return;
+            }
+        };
 
-        event::collateral_seized(&env, loan_key.clone());
-    }
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
 
+        _accrue_interest(&env, &mut loan);
 
-    fn get_loan(env: Env, loan_key: u64) -> Loan {
-        env.storage().persistent().get(&DataKey::Loan(loan_key.clone())).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist))
+        // principal, interest and penalty interest are taken from the collateral in the order of the list
+        let mut debt = loan.loan_amount + loan.accrued_interest;
+        let loan_asset_price = terms.loan_asset.lastprice(&env);
+
+        for (index, collateral) in loan.collateral.iter().enumerate() {
+            let mut seized = 0;
+
+            if debt > 0 {
+                let collateral_price = terms.collateral_assets.get_unchecked(index as u32).lastprice(&env);
+
+                if collateral_price == 0 || loan_asset_price == 0 {
+                    panic_with_error!(&env, Error::OracleError);
+                }
+
+                // rounding is in favor of the lender, the borrower gets the remainder
+                seized = _collateral_for_debt_rounded_up(&env, debt, collateral_price, loan_asset_price);
+
+                if seized < collateral.amount {
+                    debt = 0;
+                } else {
+                    seized = collateral.amount;
+                    debt -= _debt_for_collateral(&env, seized, collateral_price, loan_asset_price, 0).min(debt);
+                }
+            }
+
+            // transfering the seized part to lender and the surplus to borrower
+            if seized > 0 {
+                _create_claimable_balance(&env, &collateral.asset_contract, &env.current_contract_address(), &lender, seized);
+            }
+            if collateral.amount > seized {
+                _create_claimable_balance(&env, &collateral.asset_contract, &env.current_contract_address(), &borrower, collateral.amount - seized);
+            }
+        }
+
+        _remove_loan(&env, loan_key, &loan);
+
+        event::collateral_seized(&env, loan_key);
     }
 
-    fn get_loans(env: Env, user: Address) -> Vec<u64> {
-        env.storage().persistent().get(&DataKey::Loans(user.clone())).unwrap_or(Vec::new(&env))
+    fn liquidate(env: Env, loan_key: u64, liquidator: Address, asset_contract: Address, amount: i128) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        let terms = match loan.liquidation.clone() {
+            Liquidation::Oracle(terms) => terms,
+            _ => panic_with_error!(&env, Error::LiquidationDisabled)
+        };
+
+        let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+
+        // anyone can liquidate collateral once it's seizable at oracle prices
+        liquidator.require_auth();
+
+        _accrue_interest(&env, &mut loan);
+
+        if !loan.liquidatable(&env) {
+            panic_with_error!(&env, Error::CollateralNotSeizable);
+        }
+
+        // amount of debt to cover
+        let outstanding = loan.loan_amount + loan.accrued_interest;
+        if amount <= 0 || amount > outstanding {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        let index = _collateral_index(&env, &loan, &asset_contract);
+        let mut collateral = loan.collateral.get_unchecked(index);
+
+        let collateral_price = terms.collateral_assets.get_unchecked(index).lastprice(&env);
+        let loan_asset_price = terms.loan_asset.lastprice(&env);
+
+        if collateral_price == 0 || loan_asset_price == 0 {
+            panic_with_error!(&env, Error::OracleError);
+        }
+
+        // collateral worth the covered debt plus the bonus, limited by the available collateral
+        let mut covered = amount;
+        let mut liquidated = _collateral_for_debt(&env, covered, collateral_price, loan_asset_price, terms.bonus);
+
+        if liquidated > collateral.amount {
+            liquidated = collateral.amount;
+            covered = _debt_for_collateral(&env, liquidated, collateral_price, loan_asset_price, terms.bonus);
+        }
+
+        if liquidated == 0 || covered == 0 {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        if liquidator == lender {
+            // transfering liquidated collateral from smart contract to lender
+            _create_claimable_balance(&env, &asset_contract, &env.current_contract_address(), &lender, liquidated);
+        } else {
+            // the liquidator repays the covered debt to lender and receives the liquidated collateral
+            _create_claimable_balance(&env, &loan.loan_asset, &liquidator, &lender, covered);
+            _transfer_tokens(&env, &asset_contract, &env.current_contract_address(), &liquidator, liquidated);
+        }
+
+        collateral.amount -= liquidated;
+        loan.collateral.set(index, collateral);
+
+        _apply_payment(&mut loan, covered);
+
+        event::collateral_liquidated(&env, loan_key, liquidator, asset_contract, liquidated, covered);
+
+        if loan.loan_amount == 0 && loan.accrued_interest == 0 {
+            _close_repaid_loan(&env, loan_key, &loan);
+            return;
+        }
+
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::Loan(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
     }
 
-    fn get_interest(env: Env, loan_key: u64) -> i128 {
-        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key.clone())).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+    fn update_interest_rate(env: Env, loan_key: u64) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
-        _calculate_interest(&env, &loan)
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        if let FloatingRate::Disabled = loan.floating_rate {
+            panic_with_error!(&env, Error::FloatingRateDisabled);
+        }
+
+        _accrue_interest(&env, &mut loan);
+
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::Loan(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
+
+        event::interest_rate_updated(&env, loan_key, loan.daily_interest_rate);
     }
-}
 
-#[contractimpl]
-impl ClaimableBalanceTrait for P2PLendingContract {
-    fn withdraw(env: Env, user: Address, token: Address) {
-        let mut balances: Map<Address, i128> = env.storage().persistent().get(&DataKey::Balances(user.clone())).unwrap_or(Map::new(&env));
+    fn bid(env: Env, loan_key: u64, bidder: Address) {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
-        let balance = balances.get(token.clone()).unwrap_or(0);
+        let auction: Auction = env.storage().persistent().get(&DataKey::Auction(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::AuctionNotExist));
 
-        if balance == 0 {
-            panic_with_error!(&env, Error::NotAuthorized);
+        bidder.require_auth();
+
+        let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
+
+        // the debt was fixed when the auction started
+        let price = auction.price(env.ledger().timestamp());
+        let debt = loan.loan_amount + loan.accrued_interest;
+
+        // transfering the bid to lender up to the debt and the rest to borrower
+        if price > 0 {
+            _create_claimable_balance(&env, &loan.loan_asset, &bidder, &lender, price.min(debt));
+        }
+        if price > debt {
+            _create_claimable_balance(&env, &loan.loan_asset, &bidder, &borrower, price - debt);
         }
 
-        _transfer_tokens(&env, &token, &env.current_contract_address(), &user, balance);
+        // transfering all collateral assets from smart contract to bidder
+        for collateral in loan.collateral.iter() {
+            if collateral.amount > 0 {
+                _transfer_tokens(&env, &collateral.asset_contract, &env.current_contract_address(), &bidder, collateral.amount);
+            }
+        }
 
-        balances.remove(token.clone());
+        _remove_loan(&env, loan_key, &loan);
 
-        env.storage().persistent().set(&DataKey::Balances(user.clone()), &balances);
-        env.storage().persistent().extend_ttl(&DataKey::Balances(user.clone()), PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+        event::auction_settled(&env, loan_key, bidder, price);
     }
 
-    fn get_balances(env: Env, user: Address) -> Map<Address, i128> {
-        env.storage().persistent().get(&DataKey::Balances(user.clone())).unwrap_or(Map::new(&env))
+    fn add_collateral(env: Env, loan_key: u64, asset_contract: Address, amount: i128) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        let index = _collateral_index(&env, &loan, &asset_contract);
+        let mut collateral = loan.collateral.get_unchecked(index);
+
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
+        borrower.require_auth();
+
+        if amount <= 0 {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        // transfering additional collateral to the smart contract
+        _transfer_tokens(&env, &collateral.asset_contract, &borrower, &env.current_contract_address(), amount);
+
+        collateral.amount += amount;
+        loan.collateral.set(index, collateral);
+
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::Loan(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
+
+        event::collateral_added(&env, loan_key, asset_contract, amount);
     }
-}
 
-fn _modify_loan_list(
-    env: &Env,
-    user: &Address,
-    loan_key: u64,
-    add: bool
-) {
-    let mut loan_list: Vec<u64> = env.storage().persistent().get(&DataKey::Loans(user.clone())).unwrap_or(Vec::new(env));
+    fn set_withdrawal_limit(env: Env, loan_key: u64, collateral_assets: Vec<OracleAsset>, loan_asset: OracleAsset, min_ratio: u32) {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
-    if add {
-        if loan_list.first_index_of(loan_key).is_none() {
-            loan_list.push_front(loan_key);
+        let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+        lender.require_auth();
+
+        // one oracle asset for every collateral asset
+        if loan.collateral.is_empty() || collateral_assets.len() != loan.collateral.len() {
+            panic_with_error!(&env, Error::InvalidCollateral);
         }
-    } else {
-        let index = loan_list.first_index_of(loan_key);
-        if index.is_some() {
-            loan_list.remove(index.unwrap());
+
+        if min_ratio == 0 {
+            panic_with_error!(&env, Error::InvalidAmount);
         }
+
+        let withdrawal_limit = WithdrawalLimit {
+            collateral_assets,
+            loan_asset,
+            min_ratio
+        };
+
+        env.storage().persistent().set(&DataKey::WithdrawalLimit(loan_key), &withdrawal_limit);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::WithdrawalLimit(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
     }
 
-    env.storage().persistent().set(&DataKey::Loans(user.clone()), &loan_list);
+    fn withdraw_collateral(env: Env, loan_key: u64, asset_contract: Address, amount: i128) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
 
-    env.storage().persistent().extend_ttl(
-        &DataKey::Loans(user.clone()),
-        PERSISTENT_LIFETIME_THRESHOLD,
-        PERSISTENT_BUMP_AMOUNT
-    );
-}
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
 
-fn _calculate_interest(
-    env: &Env,
-    loan: &Loan
-) -> i128 {
-    if loan.status != LoanStatus::InProgress {
-        return 0;
+        let index = _collateral_index(&env, &loan, &asset_contract);
+        let mut collateral = loan.collateral.get_unchecked(index);
+
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
+        borrower.require_auth();
+
+        if amount <= 0 || amount > collateral.amount {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        // withdrawals are only possible with a limit set by the lender
+        let withdrawal_limit: WithdrawalLimit = env.storage().persistent().get(&DataKey::WithdrawalLimit(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::CollateralNotWithdrawable));
+
+        collateral.amount -= amount;
+        loan.collateral.set(index, collateral.clone());
+
+        // the remaining collateral has to cover the debt with the required ratio
+        let collateral_value = loan.try_collateral_value(&env, &withdrawal_limit.collateral_assets, &mut Vec::new(&env)).unwrap_or_else(|error| panic_with_error!(&env, error));
+        let loan_asset_price = withdrawal_limit.loan_asset.lastprice(&env);
+        let debt = loan.loan_amount + _calculate_interest(&env, &loan);
+
+        let collateral_value = match collateral_value {
+            Some(collateral_value) if loan_asset_price != 0 => collateral_value,
+            _ => panic_with_error!(&env, Error::CollateralNotWithdrawable)
+        };
+
+        if _below_collateral_ratio(&env, &collateral_value, loan_asset_price, debt, withdrawal_limit.min_ratio) {
+            panic_with_error!(&env, Error::CollateralNotWithdrawable);
+        }
+
+        // and can't become seizable
+        if loan.seizable(&env) {
+            panic_with_error!(&env, Error::CollateralNotWithdrawable);
+        }
+
+        _transfer_tokens(&env, &collateral.asset_contract, &env.current_contract_address(), &borrower, amount);
+
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::Loan(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
+
+        event::collateral_withdrawn(&env, loan_key, asset_contract, amount);
     }
 
-    let ledger = env.ledger();
-    let loan_duration = ((ledger.timestamp() - loan.timestamp) / 86400) + 1;
+    fn refinance(env: Env, loan_key: u64, lender: Address, max_loan_term: u32, daily_interest_rate: u32) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
+        let current_lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+
+        if lender == borrower || lender == current_lender {
+            panic_with_error!(&env, Error::InvalidLender);
+        }
+
+        // both the borrower and the new lender agree on the new terms
+        borrower.require_auth();
+        lender.require_auth();
+
+        _accrue_interest(&env, &mut loan);
+
+        // the rate of a floating loan follows its reference
+        if matches!(loan.floating_rate, FloatingRate::Oracle(_)) && daily_interest_rate != loan.daily_interest_rate {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        // the restarted installment schedule has to fit in the new loan term
+        if let Installments::Schedule(schedule) = loan.installments.clone() {
+            if !schedule.is_valid(max_loan_term) {
+                panic_with_error!(&env, Error::InvalidAmount);
+            }
+        }
+
+        // transfering loan amount + interest from the new lender to the current lender
+        let payoff = loan.loan_amount + loan.accrued_interest;
+        _create_claimable_balance(&env, &loan.loan_asset, &lender, &current_lender, payoff);
 
-    let interest = (loan.loan_amount * loan_duration as i128 * loan.daily_interest_rate as i128) / 10000;
+        // terms and the position token set by the current lender are dropped
+        _remove_amendment(&env, loan_key, &loan);
+        env.storage().persistent().remove(&DataKey::WithdrawalLimit(loan_key));
+        env.storage().persistent().remove(&DataKey::PositionToken(loan_key));
 
-    interest
+        // the collateral stays in the smart contract, the paid off interest becomes principal
+        let ledger = env.ledger();
+
+        loan.lender = Some(lender.clone());
+        loan.loan_amount = payoff;
+        loan.accrued_interest = 0;
+        loan.uncompounded_interest = 0;
+        loan.max_loan_term = max_loan_term;
+        loan.daily_interest_rate = daily_interest_rate;
+        loan.timestamp = ledger.timestamp();
+        loan.interest_timestamp = loan.timestamp;
+        _start_installments(&mut loan);
+
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::Loan(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
+
+        _modify_loan_list(&env, &current_lender, loan_key, false);
+        _modify_loan_list(&env, &lender, loan_key, true);
+
+        event::loan_refinanced(&env, loan_key, lender);
+    }
+
+    fn transfer_position(env: Env, loan_key: u64, lender: Address, new_lender: Address, price_asset: Option<Address>, price: i128) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        if loan.lender != Some(lender.clone()) {
+            panic_with_error!(&env, Error::NotAuthorized);
+        }
+
+        if new_lender == lender {
+            panic_with_error!(&env, Error::InvalidLender);
+        }
+
+        // the new lender takes over the position even when it's given away
+        lender.require_auth();
+        new_lender.require_auth();
+
+        // a price has to be paid in some asset
+        if price < 0 || (price > 0 && price_asset.is_none()) {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        // transfering the price from the new lender to the current lender
+        if let Some(price_asset) = price_asset {
+            if price > 0 {
+                _create_claimable_balance(&env, &price_asset, &new_lender, &lender, price);
+            }
+        }
+
+        // a token sold by the current lender can't move the position anymore
+        env.storage().persistent().remove(&DataKey::PositionToken(loan_key));
+
+        _assign_lender(&env, loan_key, &mut loan, &new_lender);
+    }
+
+    fn tokenize_position(env: Env, loan_key: u64, lender: Address) -> Address {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        if loan.lender != Some(lender.clone()) {
+            panic_with_error!(&env, Error::NotAuthorized);
+        }
+
+        // the token can move the position from now on
+        lender.require_auth();
+
+        // the token is deployed from a fixed wasm with the loan key as salt
+        let mut salt = [0u8; 32];
+        salt[24..].copy_from_slice(&loan_key.to_be_bytes());
+        let deployer = env.deployer().with_current_contract(BytesN::from_array(&env, &salt));
+
+        // a token deployed for an earlier position under the same key is linked again
+        let deployed_address = deployer.deployed_address();
+        let token = if position_token_contract::Client::new(&env, &deployed_address).try_loan_key().is_ok() {
+            deployed_address
+        } else {
+            let token = deployer.deploy(BytesN::from_array(&env, &POSITION_TOKEN_WASM_HASH));
+            position_token_contract::Client::new(&env, &token).initialize(&env.current_contract_address(), &loan_key);
+            token
+        };
+
+        env.storage().persistent().set(&DataKey::PositionToken(loan_key), &token);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::PositionToken(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
+
+        event::position_tokenized(&env, loan_key, token.clone());
+
+        token
+    }
+
+    fn transfer_position_by_token(env: Env, loan_key: u64, from: Address, to: Address) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+        let token: Address = env.storage().persistent().get(&DataKey::PositionToken(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::PositionNotTokenized));
+
+        // the token contract checks the holder authorization and allowances,
+        // the recipient still has to accept the position
+        token.require_auth();
+        to.require_auth();
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        if loan.lender != Some(from) {
+            panic_with_error!(&env, Error::NotAuthorized);
+        }
+
+        _assign_lender(&env, loan_key, &mut loan, &to);
+    }
+
+    fn propose_amendment(env: Env, loan_key: u64, user: Address, max_loan_term: u32, daily_interest_rate: u32, fee: i128) {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        user.require_auth();
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
+        let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+
+        if user != borrower && user != lender {
+            panic_with_error!(&env, Error::NotAuthorized);
+        }
+
+        if fee < 0 {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        // the rate of a floating loan follows its reference
+        if matches!(loan.floating_rate, FloatingRate::Oracle(_)) && daily_interest_rate != loan.daily_interest_rate {
+            panic_with_error!(&env, Error::InvalidAmount);
+        }
+
+        // the installment schedule has to fit in the new loan term
+        if let Installments::Schedule(schedule) = loan.installments.clone() {
+            if !schedule.is_valid(max_loan_term) {
+                panic_with_error!(&env, Error::InvalidAmount);
+            }
+        }
+
+        // the pending proposal has to be canceled first
+        if env.storage().persistent().has(&DataKey::Amendment(loan_key)) {
+            panic_with_error!(&env, Error::AmendmentAlreadyExist);
+        }
+
+        // fee offered by the borrower is held by the smart contract until the lender decides
+        if user == borrower && fee > 0 {
+            _transfer_tokens(&env, &loan.loan_asset, &borrower, &env.current_contract_address(), fee);
+        }
+
+        let amendment = LoanAmendment {
+            proposer: user,
+            max_loan_term,
+            daily_interest_rate,
+            fee
+        };
+
+        env.storage().persistent().set(&DataKey::Amendment(loan_key), &amendment);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::Amendment(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
+
+        event::amendment_proposed(&env, loan_key);
+    }
+
+    fn accept_amendment(env: Env, loan_key: u64, user: Address) {
+        let mut loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+        let amendment: LoanAmendment = env.storage().persistent().get(&DataKey::Amendment(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::AmendmentNotExist));
+
+        user.require_auth();
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
+        let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+
+        // only the other party can accept the proposal
+        if (user != borrower && user != lender) || user == amendment.proposer {
+            panic_with_error!(&env, Error::NotAuthorized);
+        }
+
+        if amendment.fee > 0 {
+            let fee_payer = if amendment.proposer == borrower { env.current_contract_address() } else { borrower };
+            _create_claimable_balance(&env, &loan.loan_asset, &fee_payer, &lender, amendment.fee);
+        }
+
+        // days started so far are charged with the current rate
+        _accrue_interest(&env, &mut loan);
+
+        loan.max_loan_term = amendment.max_loan_term;
+
+        // the rate of a floating loan follows its reference
+        if let FloatingRate::Disabled = loan.floating_rate {
+            loan.daily_interest_rate = amendment.daily_interest_rate;
+        }
+
+        env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
+
+        env.storage().persistent().extend_ttl(
+            &DataKey::Loan(loan_key),
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT
+        );
+
+        env.storage().persistent().remove(&DataKey::Amendment(loan_key));
+
+        event::loan_amended(&env, loan_key);
+    }
+
+    fn cancel_amendment(env: Env, loan_key: u64, user: Address) {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if !env.storage().persistent().has(&DataKey::Amendment(loan_key)) {
+            panic_with_error!(&env, Error::AmendmentNotExist);
+        }
+
+        user.require_auth();
+
+        let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidBorrower));
+        let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender));
+
+        // the proposer cancels, the other party rejects
+        if user != borrower && user != lender {
+            panic_with_error!(&env, Error::NotAuthorized);
+        }
+
+        _remove_amendment(&env, loan_key, &loan);
+
+        event::amendment_canceled(&env, loan_key);
+    }
+
+
+    fn get_loan(env: Env, loan_key: u64) -> Loan {
+        env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist))
+    }
+
+    fn get_loans(env: Env, user: Address) -> Vec<u64> {
+        env.storage().persistent().get(&DataKey::Loans(user.clone())).unwrap_or(Vec::new(&env))
+    }
+
+    fn get_interest(env: Env, loan_key: u64) -> i128 {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        _calculate_interest(&env, &loan)
+    }
+
+    fn get_seize_status(env: Env, loan_key: u64) -> Vec<CollateralSeizeStatus> {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        if loan.collateral.is_empty() {
+            panic_with_error!(&env, Error::InvalidCollateral);
+        }
+
+        loan.seize_status(&env)
+    }
+
+    fn get_amendment(env: Env, loan_key: u64) -> LoanAmendment {
+        env.storage().persistent().get(&DataKey::Amendment(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::AmendmentNotExist))
+    }
+
+    fn get_lender(env: Env, loan_key: u64) -> Address {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        loan.lender.unwrap_or_else(|| panic_with_error!(&env, Error::InvalidLender))
+    }
+
+    fn get_position_token(env: Env, loan_key: u64) -> Address {
+        env.storage().persistent().get(&DataKey::PositionToken(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::PositionNotTokenized))
+    }
+
+    fn get_withdrawal_limit(env: Env, loan_key: u64) -> WithdrawalLimit {
+        env.storage().persistent().get(&DataKey::WithdrawalLimit(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::CollateralNotWithdrawable))
+    }
+
+    fn get_auction(env: Env, loan_key: u64) -> Auction {
+        env.storage().persistent().get(&DataKey::Auction(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::AuctionNotExist))
+    }
+
+    fn get_auction_price(env: Env, loan_key: u64) -> i128 {
+        let auction: Auction = env.storage().persistent().get(&DataKey::Auction(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::AuctionNotExist));
+
+        auction.price(env.ledger().timestamp())
+    }
+
+    fn get_installments(env: Env, loan_key: u64) -> InstallmentStatus {
+        let loan: Loan = env.storage().persistent().get(&DataKey::Loan(loan_key)).unwrap_or_else(|| panic_with_error!(&env, Error::LoanNotExist));
+
+        let schedule = match loan.installments.clone() {
+            Installments::Schedule(schedule) => schedule,
+            Installments::Disabled => panic_with_error!(&env, Error::InstallmentsDisabled)
+        };
+
+        if loan.status != LoanStatus::InProgress {
+            panic_with_error!(&env, Error::LoanNotInProgress);
+        }
+
+        let paid = schedule.paid(&loan);
+        let due = schedule.due(&env, &loan);
+
+        // the first installment not paid and not due yet
+        let next = (paid.max(due) + 1).min(schedule.count);
+        let repaid = schedule.principal - loan.loan_amount;
+
+        InstallmentStatus {
+            paid,
+            missed: due.saturating_sub(paid),
+            next_due_timestamp: loan.timestamp + next as u64 * schedule.period as u64 * DAY_IN_SECONDS,
+            next_due_amount: (schedule.due_principal(next) - repaid).max(0) + _calculate_interest(&env, &loan)
+        }
+    }
+}
+
+#[contractimpl]
+impl ClaimableBalanceTrait for P2PLendingContract {
+    fn withdraw(env: Env, user: Address, token: Address) {
+        let mut balances: Map<Address, i128> = env.storage().persistent().get(&DataKey::Balances(user.clone())).unwrap_or(Map::new(&env));
+
+        let balance = balances.get(token.clone()).unwrap_or(0);
+
+        if balance == 0 {
+            panic_with_error!(&env, Error::NotAuthorized);
+        }
+
+        _transfer_tokens(&env, &token, &env.current_contract_address(), &user, balance);
+
+        balances.remove(token.clone());
+
+        env.storage().persistent().set(&DataKey::Balances(user.clone()), &balances);
+        env.storage().persistent().extend_ttl(&DataKey::Balances(user.clone()), PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+    }
+
+    fn get_balances(env: Env, user: Address) -> Map<Address, i128> {
+        env.storage().persistent().get(&DataKey::Balances(user.clone())).unwrap_or(Map::new(&env))
+    }
+}
+
+fn _new_loan(
+    env: &Env,
+    loan_key: u64,
+    mut loan: Loan
+) {
+    let liquidation_valid = match loan.liquidation.clone() {
+        Liquidation::Disabled => true,
+        Liquidation::Oracle(terms) => terms.is_valid(loan.collateral.len()),
+        Liquidation::Auction(terms) => terms.is_valid(loan.collateral.len())
+    };
+
+    if !liquidation_valid {
+        panic_with_error!(env, Error::InvalidCollateral);
+    }
+
+    if let FloatingRate::Oracle(terms) = loan.floating_rate.clone() {
+        if !terms.is_valid() {
+            panic_with_error!(env, Error::InvalidAmount);
+        }
+
+        // the initial rate is charged while the reference is unavailable
+        loan.daily_interest_rate = loan.daily_interest_rate.clamp(terms.floor, terms.cap);
+    }
+
+    if let Installments::Schedule(schedule) = loan.installments.clone() {
+        if !schedule.is_valid(loan.max_loan_term) {
+            panic_with_error!(env, Error::InvalidAmount);
+        }
+    }
+
+    // a loan is either uncollateralized or backed by a basket of distinct collateral assets
+    if loan.collateral.len() > MAX_COLLATERAL_ASSETS {
+        panic_with_error!(env, Error::InvalidCollateral);
+    }
+
+    for (index, collateral) in loan.collateral.iter().enumerate() {
+        if !collateral.is_valid(&loan) {
+            panic_with_error!(env, Error::InvalidCollateral);
+        }
+
+        if loan.collateral.iter().skip(index + 1).any(|other| other.asset_contract == collateral.asset_contract) {
+            panic_with_error!(env, Error::InvalidCollateral);
+        }
+    }
+
+    match loan.status { 
+        LoanStatus::WaitingForLender => {
+            let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(env, Error::InvalidBorrower));
+            borrower.require_auth();
+
+            // transfering collateral to the smart contract
+            for collateral in loan.collateral.iter() {
+                _transfer_tokens(env, &collateral.asset_contract, &borrower, &env.current_contract_address(), collateral.amount);
+            }
+
+            _modify_loan_list(env, &borrower, loan_key, true);
+        },
+        LoanStatus::WaitingForBorrower => {
+            let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(env, Error::InvalidLender));
+            lender.require_auth();
+
+            // transfering loan amount to the smart contract
+            _transfer_tokens(env, &loan.loan_asset, &lender, &env.current_contract_address(), loan.loan_amount);
+
+            _modify_loan_list(env, &lender, loan_key, true);
+        },
+        LoanStatus::InProgress | LoanStatus::Auction => {
+            // can't create a loan with in progress status
+            panic_with_error!(env, Error::LoanInProgress);
+        }
+    }
+
+    env.storage().persistent().set(&DataKey::Loan(loan_key), &loan);
+
+    env.storage().persistent().extend_ttl(
+        &DataKey::Loan(loan_key),
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT
+    );
+
+    event::new_loan(env, loan_key);
+}
+
+fn _next_loan_key(
+    env: &Env
+) -> u64 {
+    let mut loan_counter: u64 = env.storage().instance().get(&DataKey::LoanCounter).unwrap_or(0);
+    loan_counter += 1;
+
+    env.storage().instance().set(&DataKey::LoanCounter, &loan_counter);
+    env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+
+    // caller-chosen keys can't take the assigned range
+    ASSIGNED_LOAN_KEY_START + loan_counter
+}
+
+fn _modify_loan_list(
+    env: &Env,
+    user: &Address,
+    loan_key: u64,
+    add: bool
+) {
+    let mut loan_list: Vec<u64> = env.storage().persistent().get(&DataKey::Loans(user.clone())).unwrap_or(Vec::new(env));
+
+    if add {
+        if loan_list.first_index_of(loan_key).is_none() {
+            loan_list.push_front(loan_key);
+        }
+    } else {
+        if let Some(index) = loan_list.first_index_of(loan_key) {
+            loan_list.remove(index);
+        }
+    }
+
+    env.storage().persistent().set(&DataKey::Loans(user.clone()), &loan_list);
+
+    env.storage().persistent().extend_ttl(
+        &DataKey::Loans(user.clone()),
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT
+    );
+}
+
+fn _close_repaid_loan(
+    env: &Env,
+    loan_key: u64,
+    loan: &Loan
+) {
+    // returning collateral from smart contract to borrower
+    let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(env, Error::InvalidBorrower));
+    for collateral in loan.collateral.iter() {
+        _create_claimable_balance(env, &collateral.asset_contract, &env.current_contract_address(), &borrower, collateral.amount);
+    }
+
+    _remove_loan(env, loan_key, loan);
+
+    event::loan_repaid(env, loan_key);
+}
+
+// removes a closed loan together with the data attached to it
+fn _remove_loan(
+    env: &Env,
+    loan_key: u64,
+    loan: &Loan
+) {
+    let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(env, Error::InvalidLender));
+    let borrower = loan.borrower.clone().unwrap_or_else(|| panic_with_error!(env, Error::InvalidBorrower));
+
+    _remove_amendment(env, loan_key, loan);
+    env.storage().persistent().remove(&DataKey::PositionToken(loan_key));
+    env.storage().persistent().remove(&DataKey::WithdrawalLimit(loan_key));
+    env.storage().persistent().remove(&DataKey::Auction(loan_key));
+
+    _modify_loan_list(env, &lender, loan_key, false);
+    _modify_loan_list(env, &borrower, loan_key, false);
+
+    env.storage().persistent().remove(&DataKey::Loan(loan_key));
+}
+
+// moves the lender position of an in progress loan to another address,
+// repayments and seized collateral are credited to the new lender
+fn _assign_lender(
+    env: &Env,
+    loan_key: u64,
+    loan: &mut Loan,
+    new_lender: &Address
+) {
+    let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(env, Error::InvalidLender));
+
+    if Some(new_lender.clone()) == loan.borrower || new_lender == &lender {
+        panic_with_error!(env, Error::InvalidLender);
+    }
+
+    // terms and the withdrawal limit set by the previous lender are dropped
+    _remove_amendment(env, loan_key, loan);
+    env.storage().persistent().remove(&DataKey::WithdrawalLimit(loan_key));
+
+    loan.lender = Some(new_lender.clone());
+
+    env.storage().persistent().set(&DataKey::Loan(loan_key), loan);
+
+    env.storage().persistent().extend_ttl(
+        &DataKey::Loan(loan_key),
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT
+    );
+
+    _modify_loan_list(env, &lender, loan_key, false);
+    _modify_loan_list(env, new_lender, loan_key, true);
+
+    event::position_transferred(env, loan_key, lender, new_lender.clone());
+}
+
+// drops the pending amendment of the loan, the fee held for the borrower is returned
+fn _remove_amendment(
+    env: &Env,
+    loan_key: u64,
+    loan: &Loan
+) {
+    let amendment: Option<LoanAmendment> = env.storage().persistent().get(&DataKey::Amendment(loan_key));
+
+    if let Some(amendment) = amendment {
+        if loan.borrower.as_ref() == Some(&amendment.proposer) && amendment.fee > 0 {
+            _create_claimable_balance(env, &loan.loan_asset, &env.current_contract_address(), &amendment.proposer, amendment.fee);
+        }
+
+        env.storage().persistent().remove(&DataKey::Amendment(loan_key));
+    }
+}
+
+pub(crate) fn _calculate_interest(
+    env: &Env,
+    loan: &Loan
+) -> i128 {
+    if loan.status != LoanStatus::InProgress {
+        return 0;
+    }
+
+    loan.accrued_interest + _calculate_period_interest(env, loan).0
+}
+
+// collateral value below debt value * min_ratio / 10000, no division involved
+pub(crate) fn _below_collateral_ratio(
+    env: &Env,
+    collateral_value: &I256,
+    loan_asset_price: i128,
+    debt: i128,
+    min_ratio: u32
+) -> bool {
+    let collateral_value = collateral_value.mul(&I256::from_i128(env, 10000));
+
+    let debt_value = I256::from_i128(env, loan_asset_price)
+                                .mul(&I256::from_i128(env, debt))
+                                .mul(&I256::from_i128(env, min_ratio as i128));
+
+    collateral_value < debt_value
+}
+
+// collateral amount worth the debt plus the bonus at the given prices, rounded down
+pub(crate) fn _collateral_for_debt(
+    env: &Env,
+    debt: i128,
+    collateral_price: i128,
+    loan_asset_price: i128,
+    bonus: u32
+) -> i128 {
+    I256::from_i128(env, debt)
+        .mul(&I256::from_i128(env, loan_asset_price))
+        .mul(&I256::from_i128(env, 10000 + bonus as i128))
+        .div(&I256::from_i128(env, collateral_price).mul(&I256::from_i128(env, 10000)))
+        .to_i128()
+        .unwrap_or(i128::MAX)
+}
+
+// collateral amount worth the debt at the given prices, rounded up
+fn _collateral_for_debt_rounded_up(
+    env: &Env,
+    debt: i128,
+    collateral_price: i128,
+    loan_asset_price: i128
+) -> i128 {
+    let collateral_price = I256::from_i128(env, collateral_price);
+
+    I256::from_i128(env, debt)
+        .mul(&I256::from_i128(env, loan_asset_price))
+        .add(&collateral_price.sub(&I256::from_i128(env, 1)))
+        .div(&collateral_price)
+        .to_i128()
+        .unwrap_or(i128::MAX)
+}
+
+// debt covered by the collateral amount including the bonus at the given prices, rounded down
+pub(crate) fn _debt_for_collateral(
+    env: &Env,
+    collateral_amount: i128,
+    collateral_price: i128,
+    loan_asset_price: i128,
+    bonus: u32
+) -> i128 {
+    I256::from_i128(env, collateral_amount)
+        .mul(&I256::from_i128(env, collateral_price))
+        .mul(&I256::from_i128(env, 10000))
+        .div(&I256::from_i128(env, loan_asset_price).mul(&I256::from_i128(env, 10000 + bonus as i128)))
+        .to_i128()
+        .unwrap_or(i128::MAX)
+}
+
+// hands the collateral over to the lender once the auction ended without a bid
+fn _close_auction(
+    env: &Env,
+    loan_key: u64,
+    loan: &Loan
+) {
+    let auction: Auction = env.storage().persistent().get(&DataKey::Auction(loan_key)).unwrap_or_else(|| panic_with_error!(env, Error::AuctionNotExist));
+
+    let lender = loan.lender.clone().unwrap_or_else(|| panic_with_error!(env, Error::InvalidLender));
+    lender.require_auth();
+
+    // bids are open until the end price is reached
+    if env.ledger().timestamp() < auction.end_timestamp {
+        panic_with_error!(env, Error::CollateralNotSeizable);
+    }
+
+    for collateral in loan.collateral.iter() {
+        if collateral.amount > 0 {
+            _create_claimable_balance(env, &collateral.asset_contract, &env.current_contract_address(), &lender, collateral.amount);
+        }
+    }
+
+    _remove_loan(env, loan_key, loan);
+
+    event::collateral_seized(env, loan_key);
+}
+
+// puts the whole collateral up for auction starting at a premium to its oracle value,
+// interest stops accruing and the debt is fixed until the auction is settled
+fn _start_auction(
+    env: &Env,
+    loan_key: u64,
+    loan: &mut Loan,
+    terms: &AuctionTerms
+) {
+    _accrue_interest(env, loan);
+
+    let collateral_value = loan.try_collateral_value(env, &terms.collateral_assets, &mut Vec::new(env))
+        .unwrap_or_else(|error| panic_with_error!(env, error))
+        .unwrap_or_else(|| panic_with_error!(env, Error::OracleError));
+
+    let loan_asset_price = terms.loan_asset.lastprice(env);
+    if loan_asset_price == 0 {
+        panic_with_error!(env, Error::OracleError);
+    }
+
+    // oracle value of the collateral in loan asset
+    let value = collateral_value.div(&I256::from_i128(env, loan_asset_price));
+
+    let scaled = |bps: u32| value
+        .mul(&I256::from_i128(env, bps as i128))
+        .div(&I256::from_i128(env, 10000))
+        .to_i128()
+        .unwrap_or(i128::MAX);
+
+    let timestamp = env.ledger().timestamp();
+
+    let auction = Auction {
+        start_price: scaled(10000 + terms.start_premium),
+        end_price: scaled(10000 - terms.max_discount),
+        start_timestamp: timestamp,
+        end_timestamp: timestamp + terms.duration
+    };
+
+    loan.status = LoanStatus::Auction;
+
+    // the loan terms can't change anymore
+    _remove_amendment(env, loan_key, loan);
+
+    env.storage().persistent().set(&DataKey::Loan(loan_key), loan);
+    env.storage().persistent().extend_ttl(&DataKey::Loan(loan_key), PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+
+    env.storage().persistent().set(&DataKey::Auction(loan_key), &auction);
+    env.storage().persistent().extend_ttl(&DataKey::Auction(loan_key), PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+
+    event::auction_started(env, loan_key, auction.start_price, auction.end_price);
+}
+
+// the payment covers accrued interest first, then principal
+fn _apply_payment(
+    loan: &mut Loan,
+    payment: i128
+) {
+    let interest_payment = payment.min(loan.accrued_interest);
+    loan.accrued_interest -= interest_payment;
+    loan.loan_amount -= payment - interest_payment;
+
+    // compounded interest is paid first
+    loan.uncompounded_interest = loan.uncompounded_interest.min(loan.accrued_interest);
+}
+
+// position of the asset in the collateral list of the loan
+fn _collateral_index(
+    env: &Env,
+    loan: &Loan,
+    asset_contract: &Address
+) -> u32 {
+    loan.collateral.iter()
+        .position(|collateral| &collateral.asset_contract == asset_contract)
+        .unwrap_or_else(|| panic_with_error!(env, Error::InvalidCollateral)) as u32
+}
+
+// interest accrued since the accrual anchor and the number of seconds it covers,
+// days after the loan term include the penalty rate
+fn _calculate_period_interest(
+    env: &Env,
+    loan: &Loan
+) -> (i128, u64) {
+    let ledger = env.ledger();
+
+    // the current day is already paid
+    if ledger.timestamp() < loan.interest_timestamp {
+        return (0, 0);
+    }
+
+    match loan.interest_model {
+        InterestModel::DailySimple | InterestModel::DailyCompound => {
+            // every started day is charged in full
+            let loan_duration = ((ledger.timestamp() - loan.interest_timestamp) / DAY_IN_SECONDS) + 1;
+
+            // days of the loan charged before the accrual anchor
+            let charged_days = (loan.interest_timestamp - loan.timestamp) / DAY_IN_SECONDS;
+
+            let overdue_days = (charged_days + loan_duration).saturating_sub(charged_days.max(loan.max_loan_term as u64));
+
+            let interest = if loan.interest_model == InterestModel::DailySimple {
+                (loan.loan_amount * loan_duration as i128 * loan.daily_interest_rate as i128) / 10000
+                    + (loan.loan_amount * overdue_days as i128 * loan.penalty_interest_rate as i128) / 10000
+            } else {
+                let factor = _compound_factor(env, loan.daily_interest_rate as u64, loan_duration - overdue_days)
+                    .mul(&_compound_factor(env, loan.daily_interest_rate as u64 + loan.penalty_interest_rate as u64, overdue_days))
+                    .div(&I256::from_i128(env, INTEREST_FACTOR_SCALE))
+                    .min(I256::from_i128(env, MAX_INTEREST_FACTOR));
+
+                _apply_factor(env, loan.loan_amount + loan.accrued_interest, &factor) - loan.loan_amount - loan.accrued_interest
+            };
+
+            (interest, loan_duration * DAY_IN_SECONDS)
+        },
+        InterestModel::PerSecondSimple => {
+            let interest = _per_second_interest(loan, loan.loan_amount, loan.interest_timestamp, ledger.timestamp());
+
+            (interest, ledger.timestamp() - loan.interest_timestamp)
+        },
+        InterestModel::AnnualCompound => {
+            let (interest, _) = _annual_compound_interest(loan, ledger.timestamp());
+
+            (interest, ledger.timestamp() - loan.interest_timestamp)
+        }
+    }
+}
+
+// interest accrued since the accrual anchor and the part of the accrued interest not compounded yet,
+// interest accrued in a year is added to the compounded amount on the loan anniversary
+fn _annual_compound_interest(
+    loan: &Loan,
+    timestamp: u64
+) -> (i128, i128) {
+    let mut amount = loan.loan_amount + loan.accrued_interest - loan.uncompounded_interest;
+    let mut uncompounded = loan.uncompounded_interest;
+    let mut interest = 0;
+    let mut from = loan.interest_timestamp;
+
+    while from < timestamp {
+        let anniversary = loan.timestamp + ((from - loan.timestamp) / YEAR_IN_SECONDS + 1) * YEAR_IN_SECONDS;
+        let to = anniversary.min(timestamp);
+
+        let year_interest = _per_second_interest(loan, amount, from, to);
+        uncompounded += year_interest;
+        interest += year_interest;
+
+        if to == anniversary {
+            amount += uncompounded;
+            uncompounded = 0;
+        }
+
+        from = to;
+    }
+
+    (interest, uncompounded)
+}
+
+// simple interest of the amount between the timestamps charged per second,
+// seconds after the loan term include the penalty rate
+fn _per_second_interest(
+    loan: &Loan,
+    amount: i128,
+    from: u64,
+    to: u64
+) -> i128 {
+    let term_end = loan.timestamp + loan.max_loan_term as u64 * DAY_IN_SECONDS;
+
+    let seconds = to - from;
+    let overdue_seconds = to.saturating_sub(term_end.max(from));
+
+    (amount * (seconds as i128 * loan.daily_interest_rate as i128 + overdue_seconds as i128 * loan.penalty_interest_rate as i128))
+        / (10000 * DAY_IN_SECONDS as i128)
+}
+
+// (1 + rate / 10000) ^ periods scaled by INTEREST_FACTOR_SCALE, capped at MAX_INTEREST_FACTOR
+fn _compound_factor(
+    env: &Env,
+    rate: u64,
+    periods: u64
+) -> I256 {
+    let scale = I256::from_i128(env, INTEREST_FACTOR_SCALE);
+    let max_factor = I256::from_i128(env, MAX_INTEREST_FACTOR);
+
+    let mut factor = scale.clone();
+    let mut base = I256::from_i128(env, INTEREST_FACTOR_SCALE * (10000 + rate as i128) / 10000).min(max_factor.clone());
+    let mut exponent = periods;
+
+    // every product stays below the square of the cap
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            factor = factor.mul(&base).div(&scale).min(max_factor.clone());
+        }
+
+        exponent /= 2;
+
+        if exponent > 0 {
+            base = base.mul(&base).div(&scale).min(max_factor.clone());
+        }
+    }
+
+    factor
+}
+
+// amount multiplied by a scaled factor, rounded down
+fn _apply_factor(
+    env: &Env,
+    amount: i128,
+    factor: &I256
+) -> i128 {
+    I256::from_i128(env, amount)
+        .mul(factor)
+        .div(&I256::from_i128(env, INTEREST_FACTOR_SCALE))
+        .to_i128()
+        .unwrap_or(i128::MAX)
+}
+
+// moves the interest accrued so far into the loan and advances the accrual anchor
+// past the charged time, so it is not charged twice
+fn _accrue_interest(
+    env: &Env,
+    loan: &mut Loan
+) {
+    let (interest, seconds) = _calculate_period_interest(env, loan);
+
+    if loan.interest_model == InterestModel::AnnualCompound {
+        loan.uncompounded_interest = _annual_compound_interest(loan, env.ledger().timestamp()).1;
+    }
+
+    loan.accrued_interest += interest;
+    loan.interest_timestamp += seconds;
+
+    _checkpoint_floating_rate(env, loan);
+}
+
+// the installment schedule starts over with the current principal
+fn _start_installments(
+    loan: &mut Loan
+) {
+    if let Installments::Schedule(mut schedule) = loan.installments.clone() {
+        schedule.principal = loan.loan_amount;
+        loan.installments = Installments::Schedule(schedule);
+    }
+}
+
+// moves a floating rate to its reference for the interest accrued from now on,
+// the previous rate is kept while the reference is unavailable
+fn _checkpoint_floating_rate(
+    env: &Env,
+    loan: &mut Loan
+) {
+    if let FloatingRate::Oracle(terms) = loan.floating_rate.clone() {
+        if let Some(rate) = terms.try_rate(env) {
+            loan.daily_interest_rate = rate;
+        }
+    }
 }
 
 fn _transfer_tokens(
@@ -396,7 +1653,7 @@ fn _create_claimable_balance(
         token.transfer(from, &env.current_contract_address(), &transfer_amount);    
     }
 
-    let mut balances: Map<Address, i128> = env.storage().persistent().get(&DataKey::Balances(to.clone())).unwrap_or(Map::new(&env));
+    let mut balances: Map<Address, i128> = env.storage().persistent().get(&DataKey::Balances(to.clone())).unwrap_or(Map::new(env));
 
     let mut balance = balances.get(token_address.clone()).unwrap_or(0);
 
@@ -1,4 +1,4 @@
-use soroban_sdk::{Env, Symbol};
+use soroban_sdk::{Address, Env, Symbol};
 
 pub(crate) fn new_loan(env: &Env, loan_key: u64) {
     let topics = (Symbol::new(env, "new_loan"),);
@@ -15,7 +15,72 @@ pub(crate) fn loan_repaid(env: &Env, loan_key: u64) {
     env.events().publish(topics, loan_key);
 }
 
+pub(crate) fn loan_partially_repaid(env: &Env, loan_key: u64, amount: i128) {
+    let topics = (Symbol::new(env, "loan_partially_repaid"),);
+    env.events().publish(topics, (loan_key, amount));
+}
+
+pub(crate) fn collateral_added(env: &Env, loan_key: u64, asset_contract: Address, amount: i128) {
+    let topics = (Symbol::new(env, "collateral_added"),);
+    env.events().publish(topics, (loan_key, asset_contract, amount));
+}
+
+pub(crate) fn collateral_withdrawn(env: &Env, loan_key: u64, asset_contract: Address, amount: i128) {
+    let topics = (Symbol::new(env, "collateral_withdrawn"),);
+    env.events().publish(topics, (loan_key, asset_contract, amount));
+}
+
+pub(crate) fn collateral_liquidated(env: &Env, loan_key: u64, liquidator: Address, asset_contract: Address, amount: i128, debt: i128) {
+    let topics = (Symbol::new(env, "collateral_liquidated"),);
+    env.events().publish(topics, (loan_key, liquidator, asset_contract, amount, debt));
+}
+
+pub(crate) fn interest_rate_updated(env: &Env, loan_key: u64, daily_interest_rate: u32) {
+    let topics = (Symbol::new(env, "interest_rate_updated"),);
+    env.events().publish(topics, (loan_key, daily_interest_rate));
+}
+
+pub(crate) fn auction_started(env: &Env, loan_key: u64, start_price: i128, end_price: i128) {
+    let topics = (Symbol::new(env, "auction_started"),);
+    env.events().publish(topics, (loan_key, start_price, end_price));
+}
+
+pub(crate) fn auction_settled(env: &Env, loan_key: u64, bidder: Address, price: i128) {
+    let topics = (Symbol::new(env, "auction_settled"),);
+    env.events().publish(topics, (loan_key, bidder, price));
+}
+
 pub(crate) fn collateral_seized(env: &Env, loan_key: u64) {
     let topics = (Symbol::new(env, "collateral_seized"),);
     env.events().publish(topics, loan_key);
-}
\ No newline at end of file
+}
+
+pub(crate) fn loan_refinanced(env: &Env, loan_key: u64, lender: Address) {
+    let topics = (Symbol::new(env, "loan_refinanced"),);
+    env.events().publish(topics, (loan_key, lender));
+}
+
+pub(crate) fn position_transferred(env: &Env, loan_key: u64, from: Address, to: Address) {
+    let topics = (Symbol::new(env, "position_transferred"),);
+    env.events().publish(topics, (loan_key, from, to));
+}
+
+pub(crate) fn position_tokenized(env: &Env, loan_key: u64, token: Address) {
+    let topics = (Symbol::new(env, "position_tokenized"),);
+    env.events().publish(topics, (loan_key, token));
+}
+
+pub(crate) fn amendment_proposed(env: &Env, loan_key: u64) {
+    let topics = (Symbol::new(env, "amendment_proposed"),);
+    env.events().publish(topics, loan_key);
+}
+
+pub(crate) fn amendment_canceled(env: &Env, loan_key: u64) {
+    let topics = (Symbol::new(env, "amendment_canceled"),);
+    env.events().publish(topics, loan_key);
+}
+
+pub(crate) fn loan_amended(env: &Env, loan_key: u64) {
+    let topics = (Symbol::new(env, "loan_amended"),);
+    env.events().publish(topics, loan_key);
+}
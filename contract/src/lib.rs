@@ -3,8 +3,12 @@
 mod types;
 mod event;
 mod contract;
+mod test;
 mod reflector_oracle {
     soroban_sdk::contractimport!(file = "./reflector_oracle.wasm");
 }
+mod position_token_contract {
+    soroban_sdk::contractimport!(file = "./position_token.wasm");
+}
 
-pub use crate::contract::P2PLendingContractClient;
\ No newline at end of file
+pub use crate::contract::{P2PLendingContract, P2PLendingContractClient, P2PLendingTrait, ClaimableBalanceTrait};
\ No newline at end of file
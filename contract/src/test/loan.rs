@@ -0,0 +1,1748 @@
+use soroban_sdk::{
+    testutils::{Address as _, Events, Ledger}, token, vec, Address, Env, FromVal, IntoVal, Symbol, Vec
+};
+use reflector_mock::{Asset, ReflectorMock, ReflectorMockClient};
+use crate::position_token_contract;
+use crate::contract::{P2PLendingContract, P2PLendingContractClient};
+use crate::types::*;
+use super::loan_fixture;
+
+const NOW: u64 = 1_000_000;
+const DAY: u64 = 86400;
+const PRICE_UNIT: i128 = 100_000_000_000_000;
+
+// 7 decimals of Stellar assets
+const UNIT: i128 = 10_000_000;
+
+const LOAN_AMOUNT: i128 = 1_000 * UNIT;
+const COLLATERAL_AMOUNT: i128 = 2_000 * UNIT;
+const DAILY_INTEREST_RATE: u32 = 10;
+const INITIAL_BALANCE: i128 = 10_000 * UNIT;
+
+// interest of LOAN_AMOUNT for one day
+const DAILY_INTEREST: i128 = LOAN_AMOUNT * DAILY_INTEREST_RATE as i128 / 10000;
+
+struct TestContext {
+    env: Env,
+    contract: P2PLendingContractClient<'static>,
+    oracle: ReflectorMockClient<'static>,
+    borrower: Address,
+    lender: Address,
+    loan_asset: Address,
+    collateral_asset: Address
+}
+
+impl TestContext {
+    fn new() -> Self {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger().set_timestamp(NOW);
+
+        let contract_address = env.register_contract(None, P2PLendingContract);
+        let contract = P2PLendingContractClient::new(&env, &contract_address);
+
+        let oracle_address = env.register_contract(None, ReflectorMock);
+        let oracle = ReflectorMockClient::new(&env, &oracle_address);
+
+        let admin = Address::generate(&env);
+        let loan_asset = env.register_stellar_asset_contract_v2(admin.clone()).address();
+        let collateral_asset = env.register_stellar_asset_contract_v2(admin).address();
+
+        let borrower = Address::generate(&env);
+        let lender = Address::generate(&env);
+
+        for user in [&borrower, &lender] {
+            token::StellarAssetClient::new(&env, &loan_asset).mint(user, &INITIAL_BALANCE);
+            token::StellarAssetClient::new(&env, &collateral_asset).mint(user, &INITIAL_BALANCE);
+        }
+
+        TestContext { env, contract, oracle, borrower, lender, loan_asset, collateral_asset }
+    }
+
+    fn balance(&self, asset: &Address, user: &Address) -> i128 {
+        token::Client::new(&self.env, asset).balance(user)
+    }
+
+    fn claimable(&self, asset: &Address, user: &Address) -> i128 {
+        self.contract.get_balances(user).get(asset.clone()).unwrap_or(0)
+    }
+
+    fn collateral(&self, seize_conditions: Vec<SeizeCondition>) -> Vec<Collateral> {
+        vec![&self.env, Collateral {
+            asset_contract: self.collateral_asset.clone(),
+            amount: COLLATERAL_AMOUNT,
+            seize_conditions
+        }]
+    }
+
+    fn loan_default_collateral(&self) -> Vec<Collateral> {
+        self.collateral(vec![&self.env, SeizeCondition::LoanDefault])
+    }
+
+    // loan requested by the borrower, waiting for a lender
+    fn loan_request(&self, collateral: Vec<Collateral>) -> Loan {
+        Loan {
+            borrower: Some(self.borrower.clone()),
+            collateral,
+            daily_interest_rate: DAILY_INTEREST_RATE,
+            ..loan_fixture(&self.env, &self.loan_asset, LOAN_AMOUNT)
+        }
+    }
+
+    // loan offered by the lender, waiting for a borrower
+    fn loan_offer(&self, collateral: Vec<Collateral>) -> Loan {
+        Loan {
+            borrower: None,
+            lender: Some(self.lender.clone()),
+            status: LoanStatus::WaitingForBorrower,
+            ..self.loan_request(collateral)
+        }
+    }
+
+    // in progress loan with collateral seizable on default
+    fn in_progress_loan(&self, loan_key: u64) {
+        self.contract.new_loan(&loan_key, &self.loan_request(self.loan_default_collateral()));
+        self.contract.lend(&loan_key, &self.lender);
+    }
+
+    fn set_timestamp(&self, timestamp: u64) {
+        self.env.ledger().set_timestamp(timestamp);
+    }
+
+    fn oracle_asset(&self, symbol: &str) -> OracleAsset {
+        OracleAsset {
+            oracle_contract: self.oracle.address.clone(),
+            asset_contract: None,
+            oracle_symbol: Some(Symbol::new(&self.env, symbol)),
+            max_age: 0
+        }
+    }
+
+    // sets the oracle price at the current ledger timestamp
+    fn set_price(&self, symbol: &str, price: i128) {
+        self.oracle.set_price(&Asset::Other(Symbol::new(&self.env, symbol)), &price, &self.env.ledger().timestamp());
+    }
+}
+
+#[test]
+fn test_new_loan_request() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.borrower), INITIAL_BALANCE - COLLATERAL_AMOUNT);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.contract.address), COLLATERAL_AMOUNT);
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.status, LoanStatus::WaitingForLender);
+    assert_eq!(loan.borrower, Some(ctx.borrower.clone()));
+
+    assert_eq!(ctx.contract.get_loans(&ctx.borrower), vec![&ctx.env, 1]);
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), Vec::new(&ctx.env));
+}
+
+#[test]
+fn test_new_loan_offer() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_offer(ctx.loan_default_collateral()));
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.lender), INITIAL_BALANCE - LOAN_AMOUNT);
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.contract.address), LOAN_AMOUNT);
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), vec![&ctx.env, 1]);
+}
+
+#[test]
+fn test_new_loan_errors() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+
+    assert_eq!(
+        ctx.contract.try_new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral())),
+        Err(Ok(Error::LoanAlreadyExist.into()))
+    );
+
+    let in_progress = Loan {
+        status: LoanStatus::InProgress,
+        ..ctx.loan_request(Vec::new(&ctx.env))
+    };
+    assert_eq!(ctx.contract.try_new_loan(&2, &in_progress), Err(Ok(Error::LoanInProgress.into())));
+
+    let no_borrower = Loan {
+        borrower: None,
+        ..ctx.loan_request(Vec::new(&ctx.env))
+    };
+    assert_eq!(ctx.contract.try_new_loan(&2, &no_borrower), Err(Ok(Error::InvalidBorrower.into())));
+
+    let no_lender = Loan {
+        lender: None,
+        ..ctx.loan_offer(Vec::new(&ctx.env))
+    };
+    assert_eq!(ctx.contract.try_new_loan(&2, &no_lender), Err(Ok(Error::InvalidLender.into())));
+
+    // collateral without seize conditions
+    assert_eq!(
+        ctx.contract.try_new_loan(&2, &ctx.loan_request(ctx.collateral(Vec::new(&ctx.env)))),
+        Err(Ok(Error::InvalidCollateral.into()))
+    );
+
+    // malformed condition expression
+    let not = SeizeCondition::Not(vec![&ctx.env, SeizeCondition::LoanDefault, SeizeCondition::LoanDefault]);
+    assert_eq!(
+        ctx.contract.try_new_loan(&2, &ctx.loan_request(ctx.collateral(vec![&ctx.env, not]))),
+        Err(Ok(Error::InvalidCollateral.into()))
+    );
+
+    // the same collateral asset listed twice
+    let mut collateral = ctx.loan_default_collateral();
+    collateral.append(&ctx.loan_default_collateral());
+    assert_eq!(ctx.contract.try_new_loan(&2, &ctx.loan_request(collateral)), Err(Ok(Error::InvalidCollateral.into())));
+}
+
+#[test]
+fn test_new_loan_distinct_collateral_assets() {
+    let ctx = TestContext::new();
+
+    let admin = Address::generate(&ctx.env);
+    let second_asset = ctx.env.register_stellar_asset_contract_v2(admin).address();
+    token::StellarAssetClient::new(&ctx.env, &second_asset).mint(&ctx.borrower, &INITIAL_BALANCE);
+
+    let mut collateral = ctx.loan_default_collateral();
+    collateral.push_back(Collateral {
+        asset_contract: second_asset.clone(),
+        amount: 10 * UNIT,
+        seize_conditions: vec![&ctx.env, SeizeCondition::LoanDefault]
+    });
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(collateral.clone()));
+
+    assert_eq!(ctx.contract.get_loan(&1).collateral, collateral);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.contract.address), COLLATERAL_AMOUNT);
+    assert_eq!(ctx.balance(&second_asset, &ctx.contract.address), 10 * UNIT);
+}
+
+#[test]
+fn test_create_loan() {
+    let ctx = TestContext::new();
+
+    // caller-chosen keys don't collide with the assigned ones
+    ctx.contract.new_loan(&1, &ctx.loan_request(Vec::new(&ctx.env)));
+
+    assert_eq!(ctx.contract.create_loan(&ctx.loan_request(Vec::new(&ctx.env))), ASSIGNED_LOAN_KEY_START + 1);
+
+    let loan_key = ctx.contract.create_loan(&ctx.loan_request(Vec::new(&ctx.env)));
+    assert_eq!(loan_key, ASSIGNED_LOAN_KEY_START + 2);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "new_loan"),).into_val(&ctx.env));
+    assert_eq!(u64::from_val(&ctx.env, &data), ASSIGNED_LOAN_KEY_START + 2);
+
+    assert_eq!(ctx.contract.get_loans(&ctx.borrower), vec![&ctx.env, ASSIGNED_LOAN_KEY_START + 2, ASSIGNED_LOAN_KEY_START + 1, 1]);
+
+    // the assigned range is reserved
+    assert_eq!(ctx.contract.try_new_loan(&ASSIGNED_LOAN_KEY_START, &ctx.loan_request(Vec::new(&ctx.env))), Err(Ok(Error::InvalidLoanKey.into())));
+    assert_eq!(ctx.contract.try_new_loan(&(ASSIGNED_LOAN_KEY_START + 3), &ctx.loan_request(Vec::new(&ctx.env))), Err(Ok(Error::InvalidLoanKey.into())));
+}
+
+#[test]
+fn test_cancel_loan_request() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+    ctx.contract.cancel_loan(&1);
+
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.borrower), INITIAL_BALANCE);
+    assert_eq!(ctx.contract.get_loans(&ctx.borrower), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.try_get_loan(&1).err(), Some(Ok(Error::LoanNotExist.into())));
+    assert_eq!(ctx.contract.try_cancel_loan(&1), Err(Ok(Error::LoanNotExist.into())));
+}
+
+#[test]
+fn test_cancel_loan_offer() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_offer(ctx.loan_default_collateral()));
+    ctx.contract.cancel_loan(&1);
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.lender), INITIAL_BALANCE);
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), Vec::new(&ctx.env));
+}
+
+#[test]
+fn test_cancel_loan_in_progress() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+
+    assert_eq!(ctx.contract.try_cancel_loan(&1), Err(Ok(Error::LoanInProgress.into())));
+}
+
+#[test]
+fn test_lend() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+
+    ctx.set_timestamp(NOW + 100);
+    ctx.contract.lend(&1, &ctx.lender);
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.lender), INITIAL_BALANCE - LOAN_AMOUNT);
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.borrower), INITIAL_BALANCE + LOAN_AMOUNT);
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.status, LoanStatus::InProgress);
+    assert_eq!(loan.lender, Some(ctx.lender.clone()));
+    assert_eq!(loan.timestamp, NOW + 100);
+    assert_eq!(loan.interest_timestamp, NOW + 100);
+
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), vec![&ctx.env, 1]);
+
+    // already in progress
+    assert_eq!(ctx.contract.try_lend(&1, &ctx.lender), Err(Ok(Error::LendingError.into())));
+}
+
+#[test]
+fn test_lend_errors() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.try_lend(&1, &ctx.lender), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(Vec::new(&ctx.env)));
+    assert_eq!(ctx.contract.try_lend(&1, &ctx.borrower), Err(Ok(Error::LendingError.into())));
+
+    // loan reserved for another lender
+    let reserved = Loan {
+        lender: Some(Address::generate(&ctx.env)),
+        ..ctx.loan_request(Vec::new(&ctx.env))
+    };
+    ctx.contract.new_loan(&2, &reserved);
+    assert_eq!(ctx.contract.try_lend(&2, &ctx.lender), Err(Ok(Error::InvalidLender.into())));
+
+    // offers are taken with borrow
+    ctx.contract.new_loan(&3, &ctx.loan_offer(Vec::new(&ctx.env)));
+    assert_eq!(ctx.contract.try_lend(&3, &ctx.lender), Err(Ok(Error::LendingError.into())));
+}
+
+#[test]
+fn test_borrow() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_offer(ctx.loan_default_collateral()));
+
+    ctx.set_timestamp(NOW + 100);
+    ctx.contract.borrow(&1, &ctx.borrower);
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.borrower), INITIAL_BALANCE + LOAN_AMOUNT);
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.contract.address), 0);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.borrower), INITIAL_BALANCE - COLLATERAL_AMOUNT);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.contract.address), COLLATERAL_AMOUNT);
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.status, LoanStatus::InProgress);
+    assert_eq!(loan.borrower, Some(ctx.borrower.clone()));
+    assert_eq!(loan.timestamp, NOW + 100);
+
+    assert_eq!(ctx.contract.get_loans(&ctx.borrower), vec![&ctx.env, 1]);
+
+    // already in progress
+    assert_eq!(ctx.contract.try_borrow(&1, &ctx.borrower), Err(Ok(Error::BorrowingError.into())));
+}
+
+#[test]
+fn test_borrow_errors() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.try_borrow(&1, &ctx.borrower), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_offer(Vec::new(&ctx.env)));
+    assert_eq!(ctx.contract.try_borrow(&1, &ctx.lender), Err(Ok(Error::BorrowingError.into())));
+
+    // offer reserved for another borrower
+    let reserved = Loan {
+        borrower: Some(Address::generate(&ctx.env)),
+        ..ctx.loan_offer(Vec::new(&ctx.env))
+    };
+    ctx.contract.new_loan(&2, &reserved);
+    assert_eq!(ctx.contract.try_borrow(&2, &ctx.borrower), Err(Ok(Error::InvalidBorrower.into())));
+
+    // requests are taken with lend
+    ctx.contract.new_loan(&3, &ctx.loan_request(Vec::new(&ctx.env)));
+    assert_eq!(ctx.contract.try_borrow(&3, &ctx.borrower), Err(Ok(Error::BorrowingError.into())));
+}
+
+#[test]
+fn test_get_interest() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(Vec::new(&ctx.env)));
+    assert_eq!(ctx.contract.get_interest(&1), 0);
+
+    ctx.contract.lend(&1, &ctx.lender);
+
+    // every started day is charged in full
+    assert_eq!(ctx.contract.get_interest(&1), DAILY_INTEREST);
+
+    ctx.set_timestamp(NOW + DAY - 1);
+    assert_eq!(ctx.contract.get_interest(&1), DAILY_INTEREST);
+
+    ctx.set_timestamp(NOW + DAY);
+    assert_eq!(ctx.contract.get_interest(&1), 2 * DAILY_INTEREST);
+
+    ctx.set_timestamp(NOW + 10 * DAY + 1);
+    assert_eq!(ctx.contract.get_interest(&1), 11 * DAILY_INTEREST);
+}
+
+#[test]
+fn test_interest_models() {
+    let ctx = TestContext::new();
+
+    let models = [InterestModel::PerSecondSimple, InterestModel::DailyCompound, InterestModel::AnnualCompound];
+    for (loan_key, interest_model) in models.into_iter().enumerate() {
+        ctx.contract.new_loan(&(loan_key as u64 + 1), &Loan {
+            interest_model,
+            max_loan_term: 1000,
+            ..ctx.loan_request(Vec::new(&ctx.env))
+        });
+        ctx.contract.lend(&(loan_key as u64 + 1), &ctx.lender);
+    }
+
+    // per second accrual charges only the elapsed time
+    assert_eq!(ctx.contract.get_interest(&1), 0);
+
+    ctx.set_timestamp(NOW + 60);
+    assert_eq!(ctx.contract.get_interest(&1), LOAN_AMOUNT * 60 * DAILY_INTEREST_RATE as i128 / (10000 * DAY as i128));
+
+    ctx.set_timestamp(NOW + DAY);
+    assert_eq!(ctx.contract.get_interest(&1), DAILY_INTEREST);
+
+    // daily compounding charges started days on the interest of the previous days
+    assert_eq!(ctx.contract.get_interest(&2), 2 * DAILY_INTEREST + DAILY_INTEREST / 1000);
+
+    ctx.set_timestamp(NOW + 2 * DAY);
+    assert_eq!(ctx.contract.get_interest(&2), 3 * DAILY_INTEREST + 3 * DAILY_INTEREST / 1000 + DAILY_INTEREST / 1_000_000);
+
+    // annual compounding adds the interest of the year on the loan anniversary
+    let yearly_interest = 365 * DAILY_INTEREST;
+
+    ctx.set_timestamp(NOW + 365 * DAY);
+    assert_eq!(ctx.contract.get_interest(&3), yearly_interest);
+
+    ctx.set_timestamp(NOW + 730 * DAY);
+    assert_eq!(ctx.contract.get_interest(&3), 2 * yearly_interest + yearly_interest * yearly_interest / LOAN_AMOUNT);
+
+    // accrual on a partial repayment keeps the compounding
+    ctx.contract.repay_partial(&3, &ctx.borrower, &UNIT);
+    assert_eq!(ctx.contract.get_interest(&3), 2 * yearly_interest + yearly_interest * yearly_interest / LOAN_AMOUNT - UNIT);
+    assert_eq!(ctx.contract.get_loan(&3).interest_timestamp, NOW + 730 * DAY);
+
+    // the penalty is added to the rate without overflowing
+    ctx.contract.new_loan(&4, &Loan {
+        interest_model: InterestModel::DailyCompound,
+        daily_interest_rate: u32::MAX,
+        penalty_interest_rate: u32::MAX,
+        max_loan_term: 1,
+        ..ctx.loan_request(Vec::new(&ctx.env))
+    });
+    ctx.contract.lend(&4, &ctx.lender);
+
+    ctx.set_timestamp(NOW + 731 * DAY);
+    assert!(ctx.contract.get_interest(&4) > LOAN_AMOUNT * 429_000 * 858_000);
+}
+
+#[test]
+fn test_daily_compound_long_horizon() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &Loan {
+        interest_model: InterestModel::DailyCompound,
+        daily_interest_rate: 100,
+        penalty_interest_rate: 100,
+        ..ctx.loan_request(ctx.loan_default_collateral())
+    });
+    ctx.contract.lend(&1, &ctx.lender);
+
+    // 30 years at 1% and 2% a day, the factor stops at a trillion
+    ctx.set_timestamp(NOW + 30 * 365 * DAY);
+    assert_eq!(ctx.contract.get_interest(&1), LOAN_AMOUNT * 1_000_000_000_000 - LOAN_AMOUNT);
+
+    // the loan can still be closed
+    ctx.contract.seize(&1);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+}
+
+#[test]
+fn test_annual_compound_checkpoints() {
+    let ctx = TestContext::new();
+
+    for loan_key in [1, 2] {
+        ctx.contract.new_loan(&loan_key, &Loan {
+            interest_model: InterestModel::AnnualCompound,
+            max_loan_term: 1000,
+            ..ctx.loan_request(Vec::new(&ctx.env))
+        });
+        ctx.contract.lend(&loan_key, &ctx.lender);
+    }
+
+    // interest accrued mid-year is compounded on the anniversary only
+    for days in [100, 200, 465, 730, 800] {
+        ctx.set_timestamp(NOW + days * DAY);
+        ctx.contract.propose_amendment(&1, &ctx.lender, &1000, &DAILY_INTEREST_RATE, &0);
+        ctx.contract.accept_amendment(&1, &ctx.borrower);
+
+        assert_eq!(ctx.contract.get_interest(&1), ctx.contract.get_interest(&2));
+    }
+
+    let yearly_interest = 365 * DAILY_INTEREST;
+    let compounded = LOAN_AMOUNT + 2 * yearly_interest + yearly_interest * yearly_interest / LOAN_AMOUNT;
+
+    ctx.set_timestamp(NOW + 900 * DAY);
+    assert_eq!(ctx.contract.get_interest(&1), compounded - LOAN_AMOUNT + compounded * 170 * DAILY_INTEREST_RATE as i128 / 10000);
+    assert_eq!(ctx.contract.get_interest(&1), ctx.contract.get_interest(&2));
+}
+
+#[test]
+fn test_floating_rate() {
+    let ctx = TestContext::new();
+
+    let terms = FloatingRateTerms {
+        reference: ctx.oracle_asset("SOFR"),
+        spread: -50,
+        floor: 20,
+        cap: 80
+    };
+
+    assert_eq!(ctx.contract.try_new_loan(&1, &Loan {
+        floating_rate: FloatingRate::Oracle(FloatingRateTerms { floor: 100, ..terms.clone() }),
+        ..ctx.loan_request(Vec::new(&ctx.env))
+    }), Err(Ok(Error::InvalidAmount.into())));
+
+    ctx.contract.new_loan(&1, &Loan {
+        floating_rate: FloatingRate::Oracle(terms.clone()),
+        ..ctx.loan_request(Vec::new(&ctx.env))
+    });
+
+    // 365% a year is 100 basis points a day, minus the spread
+    ctx.set_price("SOFR", PRICE_UNIT * 365 / 100);
+    ctx.contract.lend(&1, &ctx.lender);
+    assert_eq!(ctx.contract.get_loan(&1).daily_interest_rate, 50);
+
+    // the rate is capped and charged from the checkpoint on
+    ctx.set_timestamp(NOW + 2 * DAY);
+    ctx.set_price("SOFR", PRICE_UNIT * 730 / 100);
+    ctx.contract.update_interest_rate(&1);
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.daily_interest_rate, 80);
+    assert_eq!(loan.accrued_interest, 3 * LOAN_AMOUNT * 50 / 10000);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "interest_rate_updated"),).into_val(&ctx.env));
+    assert_eq!(<(u64, u32)>::from_val(&ctx.env, &data), (1, 80));
+
+    ctx.set_timestamp(NOW + 3 * DAY);
+    assert_eq!(ctx.contract.get_interest(&1), 3 * LOAN_AMOUNT * 50 / 10000 + LOAN_AMOUNT * 80 / 10000);
+
+    // the rate doesn't go below the floor
+    ctx.set_price("SOFR", 0);
+    ctx.contract.update_interest_rate(&1);
+    assert_eq!(ctx.contract.get_loan(&1).daily_interest_rate, 20);
+
+    // the last rate is kept while the reference is unavailable
+    ctx.set_timestamp(NOW + 10 * DAY);
+    ctx.contract.repay_partial(&1, &ctx.borrower, &UNIT);
+    assert_eq!(ctx.contract.get_loan(&1).daily_interest_rate, 20);
+
+    // amendments and refinancing keep the rate on its reference
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &ctx.lender, &60, &100, &0), Err(Ok(Error::InvalidAmount.into())));
+    ctx.contract.propose_amendment(&1, &ctx.lender, &60, &20, &0);
+
+    ctx.set_timestamp(NOW + 11 * DAY);
+    ctx.set_price("SOFR", PRICE_UNIT * 365 / 100);
+    ctx.contract.accept_amendment(&1, &ctx.borrower);
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!((loan.max_loan_term, loan.daily_interest_rate), (60, 50));
+
+    let new_lender = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&new_lender, &INITIAL_BALANCE);
+
+    ctx.set_price("SOFR", PRICE_UNIT * 730 / 100);
+    assert_eq!(ctx.contract.try_refinance(&1, &new_lender, &30, &50), Err(Ok(Error::InvalidAmount.into())));
+    ctx.contract.refinance(&1, &new_lender, &30, &80);
+    assert_eq!(ctx.contract.get_loan(&1).daily_interest_rate, 80);
+
+    // fixed rate loans
+    ctx.in_progress_loan(2);
+    assert_eq!(ctx.contract.try_update_interest_rate(&2), Err(Ok(Error::FloatingRateDisabled.into())));
+
+    // the initial rate is kept within the bounds while the reference is unavailable
+    let oracle_down = FloatingRateTerms { reference: ctx.oracle_asset("EURIBOR"), ..terms };
+    for (loan_key, daily_interest_rate, expected_rate) in [(3, 5, 20), (4, 500, 80), (5, 30, 30)] {
+        ctx.contract.new_loan(&loan_key, &Loan {
+            daily_interest_rate,
+            floating_rate: FloatingRate::Oracle(oracle_down.clone()),
+            ..ctx.loan_request(Vec::new(&ctx.env))
+        });
+        assert_eq!(ctx.contract.get_loan(&loan_key).daily_interest_rate, expected_rate);
+
+        ctx.contract.lend(&loan_key, &ctx.lender);
+        assert_eq!(ctx.contract.get_loan(&loan_key).daily_interest_rate, expected_rate);
+    }
+}
+
+#[test]
+fn test_penalty_interest() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &Loan {
+        penalty_interest_rate: 2 * DAILY_INTEREST_RATE,
+        ..ctx.loan_request(Vec::new(&ctx.env))
+    });
+    ctx.contract.lend(&1, &ctx.lender);
+
+    // no penalty within the loan term
+    ctx.set_timestamp(NOW + 29 * DAY);
+    assert_eq!(ctx.contract.get_interest(&1), 30 * DAILY_INTEREST);
+
+    // overdue days are charged the penalty rate on top
+    ctx.set_timestamp(NOW + 31 * DAY);
+    assert_eq!(ctx.contract.get_interest(&1), 32 * DAILY_INTEREST + 2 * 2 * DAILY_INTEREST);
+
+    // days paid by a partial repayment are not charged again
+    ctx.set_timestamp(NOW + 28 * DAY);
+    ctx.contract.repay_partial(&1, &ctx.borrower, &(29 * DAILY_INTEREST));
+    assert_eq!(ctx.contract.get_interest(&1), 0);
+
+    ctx.set_timestamp(NOW + 30 * DAY);
+    assert_eq!(ctx.contract.get_interest(&1), 2 * DAILY_INTEREST + 2 * DAILY_INTEREST);
+}
+
+#[test]
+fn test_repay() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+
+    ctx.set_timestamp(NOW + 2 * DAY);
+    ctx.contract.repay(&1, &ctx.borrower);
+
+    let repaid = LOAN_AMOUNT + 3 * DAILY_INTEREST;
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.borrower), INITIAL_BALANCE + LOAN_AMOUNT - repaid);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), repaid);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), COLLATERAL_AMOUNT);
+
+    assert_eq!(ctx.contract.get_loans(&ctx.borrower), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.try_get_loan(&1).err(), Some(Ok(Error::LoanNotExist.into())));
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "loan_repaid"),).into_val(&ctx.env));
+    assert_eq!(u64::from_val(&ctx.env, &data), 1);
+}
+
+#[test]
+fn test_repay_by_third_party() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+
+    let payer = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&payer, &INITIAL_BALANCE);
+
+    ctx.contract.repay(&1, &payer);
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &payer), INITIAL_BALANCE - LOAN_AMOUNT - DAILY_INTEREST);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), COLLATERAL_AMOUNT);
+}
+
+#[test]
+fn test_repay_errors() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.try_repay(&1, &ctx.borrower), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(Vec::new(&ctx.env)));
+    assert_eq!(ctx.contract.try_repay(&1, &ctx.borrower), Err(Ok(Error::LoanNotInProgress.into())));
+}
+
+#[test]
+fn test_repay_partial() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+
+    // the first payment covers the interest of the first day and part of the principal
+    ctx.contract.repay_partial(&1, &ctx.borrower, &(DAILY_INTEREST + 400 * UNIT));
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.loan_amount, 600 * UNIT);
+    assert_eq!(loan.accrued_interest, 0);
+    assert_eq!(loan.interest_timestamp, NOW + DAY);
+    assert_eq!(ctx.contract.get_interest(&1), 0);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), DAILY_INTEREST + 400 * UNIT);
+
+    // the second day is charged on the remaining principal
+    ctx.set_timestamp(NOW + DAY);
+    let second_day_interest = 600 * UNIT * DAILY_INTEREST_RATE as i128 / 10000;
+    assert_eq!(ctx.contract.get_interest(&1), second_day_interest);
+
+    // a payment below the accrued interest leaves the principal untouched
+    ctx.contract.repay_partial(&1, &ctx.borrower, &(second_day_interest / 2));
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.loan_amount, 600 * UNIT);
+    assert_eq!(loan.accrued_interest, second_day_interest / 2);
+    assert_eq!(ctx.contract.get_interest(&1), second_day_interest / 2);
+
+    // the loan term is still measured from the original start
+    assert_eq!(loan.timestamp, NOW);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "loan_partially_repaid"),).into_val(&ctx.env));
+    assert_eq!(<(u64, i128)>::from_val(&ctx.env, &data), (1, second_day_interest / 2));
+
+    // overpayment is capped at the outstanding balance and closes the loan
+    let borrower_balance = ctx.balance(&ctx.loan_asset, &ctx.borrower);
+    ctx.contract.repay_partial(&1, &ctx.borrower, &LOAN_AMOUNT);
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.borrower), borrower_balance - 600 * UNIT - second_day_interest / 2);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), DAILY_INTEREST + LOAN_AMOUNT + second_day_interest);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), COLLATERAL_AMOUNT);
+    assert_eq!(ctx.contract.try_get_loan(&1).err(), Some(Ok(Error::LoanNotExist.into())));
+    assert_eq!(ctx.contract.get_loans(&ctx.borrower), Vec::new(&ctx.env));
+}
+
+#[test]
+fn test_repay_partial_errors() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(Vec::new(&ctx.env)));
+    assert_eq!(ctx.contract.try_repay_partial(&1, &ctx.borrower, &100), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.lend(&1, &ctx.lender);
+    assert_eq!(ctx.contract.try_repay_partial(&1, &ctx.borrower, &0), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_repay_partial(&1, &ctx.borrower, &-1), Err(Ok(Error::InvalidAmount.into())));
+}
+
+#[test]
+fn test_seize_on_default() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+
+    // the 30th day is still within the term
+    ctx.set_timestamp(NOW + 29 * DAY);
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    ctx.set_timestamp(NOW + 30 * DAY);
+    ctx.contract.seize(&1);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+    assert_eq!(ctx.contract.get_loans(&ctx.borrower), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.try_get_loan(&1).err(), Some(Ok(Error::LoanNotExist.into())));
+
+    let (_, topics, _) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "collateral_seized"),).into_val(&ctx.env));
+}
+
+#[test]
+fn test_seize_after_grace_period() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &Loan {
+        grace_period: 3,
+        ..ctx.loan_request(ctx.loan_default_collateral())
+    });
+    ctx.contract.lend(&1, &ctx.lender);
+
+    ctx.set_timestamp(NOW + 32 * DAY);
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    ctx.set_timestamp(NOW + 33 * DAY);
+    ctx.contract.seize(&1);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+}
+
+#[test]
+fn test_seize_on_oracle_price() {
+    let ctx = TestContext::new();
+
+    // seizable when the collateral is worth less than the loan
+    let condition = SeizeCondition::ReflectorOracle(
+        ctx.oracle_asset("XLM"),
+        COLLATERAL_AMOUNT,
+        ctx.oracle_asset("USDC"),
+        LOAN_AMOUNT,
+        Comparator::Less
+    );
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.collateral(vec![&ctx.env, condition])));
+    ctx.contract.lend(&1, &ctx.lender);
+
+    ctx.set_price("XLM", PRICE_UNIT / 2);
+    ctx.set_price("USDC", PRICE_UNIT);
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    ctx.set_price("XLM", PRICE_UNIT / 2 - 1);
+    ctx.contract.seize(&1);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+}
+
+#[test]
+fn test_seize_errors() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::LoanNotInProgress.into())));
+
+    // nothing to seize on uncollateralized loans
+    ctx.contract.new_loan(&2, &ctx.loan_request(Vec::new(&ctx.env)));
+    ctx.contract.lend(&2, &ctx.lender);
+
+    ctx.set_timestamp(NOW + 100 * DAY);
+    assert_eq!(ctx.contract.try_seize(&2), Err(Ok(Error::InvalidCollateral.into())));
+}
+
+#[test]
+fn test_get_seize_status() {
+    let ctx = TestContext::new();
+
+    let conditions = vec![
+        &ctx.env,
+        SeizeCondition::LoanDefault,
+        SeizeCondition::ReflectorOracle(ctx.oracle_asset("XLM"), COLLATERAL_AMOUNT, ctx.oracle_asset("USDC"), LOAN_AMOUNT, Comparator::Less),
+        SeizeCondition::ReflectorOracle(ctx.oracle_asset("BTC"), 1, ctx.oracle_asset("USDC"), 1, Comparator::Less)
+    ];
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.collateral(conditions)));
+    assert_eq!(ctx.contract.try_get_seize_status(&1), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.lend(&1, &ctx.lender);
+
+    ctx.set_price("XLM", PRICE_UNIT / 4);
+    ctx.set_price("USDC", PRICE_UNIT);
+
+    // the missing BTC price doesn't hide the other conditions
+    let status = ctx.contract.get_seize_status(&1);
+    assert_eq!(status.len(), 1);
+    assert_eq!(status.get(0).unwrap().asset_contract, ctx.collateral_asset);
+
+    let status = status.get(0).unwrap().conditions;
+    assert_eq!(status.len(), 3);
+
+    assert_eq!(status.get(0).unwrap().state, SeizeConditionState::NotSatisfied);
+    assert!(status.get(0).unwrap().prices.is_empty());
+
+    let oracle_status = status.get(1).unwrap();
+    assert_eq!(oracle_status.state, SeizeConditionState::Satisfied);
+    assert_eq!(oracle_status.prices.len(), 2);
+    assert_eq!(oracle_status.prices.get(0).unwrap().price, PRICE_UNIT / 4);
+    assert_eq!(oracle_status.prices.get(0).unwrap().timestamp, NOW);
+    assert_eq!(oracle_status.prices.get(0).unwrap().records, 1);
+    assert_eq!(oracle_status.prices.get(1).unwrap().price, PRICE_UNIT);
+
+    assert_eq!(status.get(2).unwrap().state, SeizeConditionState::Unavailable(Error::OracleError as u32));
+
+    // stale prices are reported as well
+    ctx.set_timestamp(NOW + 100 * DAY);
+    let status = ctx.contract.get_seize_status(&1).get(0).unwrap().conditions;
+    assert_eq!(status.get(0).unwrap().state, SeizeConditionState::Satisfied);
+    assert_eq!(status.get(1).unwrap().state, SeizeConditionState::Unavailable(Error::OraclePriceStale as u32));
+}
+
+#[test]
+fn test_collateral_basket() {
+    let ctx = TestContext::new();
+
+    let admin = Address::generate(&ctx.env);
+    let second_asset = ctx.env.register_stellar_asset_contract_v2(admin).address();
+    token::StellarAssetClient::new(&ctx.env, &second_asset).mint(&ctx.borrower, &INITIAL_BALANCE);
+
+    // seizable when the basket is worth less than 120% of the debt
+    let basket_ratio = SeizeCondition::BasketRatio(vec![&ctx.env, ctx.oracle_asset("XLM"), ctx.oracle_asset("BTC")], ctx.oracle_asset("USDC"), 12000);
+
+    let basket = vec![
+        &ctx.env,
+        Collateral {
+            asset_contract: ctx.collateral_asset.clone(),
+            amount: COLLATERAL_AMOUNT,
+            seize_conditions: vec![&ctx.env, SeizeCondition::LoanDefault]
+        },
+        Collateral {
+            asset_contract: second_asset.clone(),
+            amount: 10 * UNIT,
+            seize_conditions: vec![&ctx.env, basket_ratio]
+        }
+    ];
+
+    // every asset is transferred in and returned on cancel
+    ctx.contract.new_loan(&1, &ctx.loan_request(basket.clone()));
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.contract.address), COLLATERAL_AMOUNT);
+    assert_eq!(ctx.balance(&second_asset, &ctx.contract.address), 10 * UNIT);
+
+    ctx.contract.cancel_loan(&1);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.borrower), INITIAL_BALANCE);
+    assert_eq!(ctx.balance(&second_asset, &ctx.borrower), INITIAL_BALANCE);
+
+    ctx.contract.new_loan(&2, &ctx.loan_offer(basket));
+    ctx.contract.borrow(&2, &ctx.borrower);
+    assert_eq!(ctx.balance(&second_asset, &ctx.contract.address), 10 * UNIT);
+
+    // 2000 * 0.5 + 10 * 25 = 1250 for 1001 of debt
+    ctx.set_price("XLM", PRICE_UNIT / 2);
+    ctx.set_price("BTC", 25 * PRICE_UNIT);
+    ctx.set_price("USDC", PRICE_UNIT);
+    assert_eq!(ctx.contract.try_seize(&2), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    let status = ctx.contract.get_seize_status(&2);
+    assert_eq!(status.len(), 2);
+    assert_eq!(status.get(1).unwrap().asset_contract, second_asset);
+    assert_eq!(status.get(1).unwrap().conditions.first().unwrap().prices.len(), 3);
+
+    // 2000 * 0.5 + 10 * 20 = 1200
+    ctx.set_price("BTC", 20 * PRICE_UNIT);
+    ctx.contract.seize(&2);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+    assert_eq!(ctx.claimable(&second_asset, &ctx.lender), 10 * UNIT);
+}
+
+#[test]
+fn test_collateral_basket_validation() {
+    let ctx = TestContext::new();
+
+    let oracle_asset = ctx.oracle_asset("XLM");
+
+    let collateral = |asset_contract: &Address, seize_condition: SeizeCondition| Collateral {
+        asset_contract: asset_contract.clone(),
+        amount: UNIT,
+        seize_conditions: vec![&ctx.env, seize_condition]
+    };
+
+    // bounded list of assets
+    let mut basket = Vec::new(&ctx.env);
+    for _ in 0..=MAX_COLLATERAL_ASSETS {
+        let asset = ctx.env.register_stellar_asset_contract_v2(Address::generate(&ctx.env)).address();
+        basket.push_back(collateral(&asset, SeizeCondition::LoanDefault));
+    }
+    assert_eq!(ctx.contract.try_new_loan(&1, &ctx.loan_offer(basket.clone())), Err(Ok(Error::InvalidCollateral.into())));
+
+    // each asset at most once
+    let first_asset = basket.get_unchecked(0).asset_contract;
+    let second_asset = basket.get_unchecked(1).asset_contract;
+
+    let basket = vec![&ctx.env, collateral(&first_asset, SeizeCondition::LoanDefault), collateral(&first_asset, SeizeCondition::LoanDefault)];
+    assert_eq!(ctx.contract.try_new_loan(&1, &ctx.loan_offer(basket)), Err(Ok(Error::InvalidCollateral.into())));
+
+    // one oracle asset for every collateral asset
+    let basket_ratio = SeizeCondition::BasketRatio(vec![&ctx.env, oracle_asset.clone()], oracle_asset.clone(), 12000);
+    let basket = vec![&ctx.env, collateral(&first_asset, basket_ratio), collateral(&second_asset, SeizeCondition::LoanDefault)];
+    assert_eq!(ctx.contract.try_new_loan(&1, &ctx.loan_offer(basket)), Err(Ok(Error::InvalidCollateral.into())));
+
+    let basket = vec![&ctx.env, collateral(&first_asset, SeizeCondition::LoanDefault), Collateral { amount: 0, ..collateral(&second_asset, SeizeCondition::LoanDefault) }];
+    assert_eq!(ctx.contract.try_new_loan(&1, &ctx.loan_offer(basket)), Err(Ok(Error::InvalidCollateral.into())));
+}
+
+#[test]
+fn test_add_collateral() {
+    let ctx = TestContext::new();
+
+    let condition = SeizeCondition::CollateralRatio(ctx.oracle_asset("XLM"), ctx.oracle_asset("USDC"), 15000);
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.collateral(vec![&ctx.env, condition])));
+    ctx.contract.lend(&1, &ctx.lender);
+
+    // collateral worth 140% of the debt
+    ctx.set_price("XLM", PRICE_UNIT * 7 / 10);
+    ctx.set_price("USDC", PRICE_UNIT);
+
+    ctx.contract.add_collateral(&1, &ctx.collateral_asset, &(500 * UNIT));
+
+    assert_eq!(ctx.contract.get_loan(&1).collateral.first().unwrap().amount, COLLATERAL_AMOUNT + 500 * UNIT);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.contract.address), COLLATERAL_AMOUNT + 500 * UNIT);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.borrower), INITIAL_BALANCE - COLLATERAL_AMOUNT - 500 * UNIT);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "collateral_added"),).into_val(&ctx.env));
+    assert_eq!(<(u64, Address, i128)>::from_val(&ctx.env, &data), (1, ctx.collateral_asset.clone(), 500 * UNIT));
+
+    // back above the required ratio
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    // the whole collateral is returned on repayment
+    ctx.contract.repay(&1, &ctx.borrower);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), COLLATERAL_AMOUNT + 500 * UNIT);
+}
+
+#[test]
+fn test_add_collateral_errors() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.try_add_collateral(&1, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+    assert_eq!(ctx.contract.try_add_collateral(&1, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.lend(&1, &ctx.lender);
+    assert_eq!(ctx.contract.try_add_collateral(&1, &ctx.collateral_asset, &0), Err(Ok(Error::InvalidAmount.into())));
+
+    ctx.contract.new_loan(&2, &ctx.loan_request(Vec::new(&ctx.env)));
+    ctx.contract.lend(&2, &ctx.lender);
+    assert_eq!(ctx.contract.try_add_collateral(&2, &ctx.collateral_asset, &UNIT), Err(Ok(Error::InvalidCollateral.into())));
+}
+
+#[test]
+fn test_withdraw_collateral() {
+    let ctx = TestContext::new();
+
+    let condition = SeizeCondition::CollateralRatio(ctx.oracle_asset("XLM"), ctx.oracle_asset("USDC"), 12000);
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.collateral(vec![&ctx.env, condition])));
+    ctx.contract.lend(&1, &ctx.lender);
+
+    ctx.set_price("XLM", PRICE_UNIT);
+    ctx.set_price("USDC", PRICE_UNIT);
+
+    // nothing can be withdrawn until the lender sets a limit
+    assert_eq!(ctx.contract.try_withdraw_collateral(&1, &ctx.collateral_asset, &UNIT), Err(Ok(Error::CollateralNotWithdrawable.into())));
+
+    ctx.contract.set_withdrawal_limit(&1, &vec![&ctx.env, ctx.oracle_asset("XLM")], &ctx.oracle_asset("USDC"), &15000);
+    assert_eq!(ctx.contract.get_withdrawal_limit(&1).min_ratio, 15000);
+
+    // 1600 of collateral for 1001 of debt
+    ctx.contract.withdraw_collateral(&1, &ctx.collateral_asset, &(400 * UNIT));
+
+    assert_eq!(ctx.contract.get_loan(&1).collateral.first().unwrap().amount, COLLATERAL_AMOUNT - 400 * UNIT);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.borrower), INITIAL_BALANCE - COLLATERAL_AMOUNT + 400 * UNIT);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "collateral_withdrawn"),).into_val(&ctx.env));
+    assert_eq!(<(u64, Address, i128)>::from_val(&ctx.env, &data), (1, ctx.collateral_asset.clone(), 400 * UNIT));
+
+    // 1500 is below 150% of the debt
+    assert_eq!(ctx.contract.try_withdraw_collateral(&1, &ctx.collateral_asset, &(100 * UNIT)), Err(Ok(Error::CollateralNotWithdrawable.into())));
+
+    // a lower limit can't make the collateral seizable
+    ctx.contract.set_withdrawal_limit(&1, &vec![&ctx.env, ctx.oracle_asset("XLM")], &ctx.oracle_asset("USDC"), &10000);
+    assert_eq!(ctx.contract.try_withdraw_collateral(&1, &ctx.collateral_asset, &(400 * UNIT)), Err(Ok(Error::CollateralNotWithdrawable.into())));
+
+    ctx.contract.withdraw_collateral(&1, &ctx.collateral_asset, &(300 * UNIT));
+    assert_eq!(ctx.contract.get_loan(&1).collateral.first().unwrap().amount, 1_300 * UNIT);
+
+    // the limit is removed with the loan
+    ctx.contract.repay(&1, &ctx.borrower);
+    assert_eq!(ctx.contract.try_get_withdrawal_limit(&1).err(), Some(Ok(Error::CollateralNotWithdrawable.into())));
+}
+
+#[test]
+fn test_withdraw_collateral_errors() {
+    let ctx = TestContext::new();
+
+    let oracle_asset = ctx.oracle_asset("XLM");
+
+    assert_eq!(ctx.contract.try_withdraw_collateral(&1, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_offer(ctx.loan_default_collateral()));
+    assert_eq!(ctx.contract.try_set_withdrawal_limit(&1, &vec![&ctx.env, oracle_asset.clone()], &oracle_asset, &0), Err(Ok(Error::InvalidAmount.into())));
+
+    // the limit can be part of the offer
+    ctx.contract.set_withdrawal_limit(&1, &vec![&ctx.env, oracle_asset.clone()], &oracle_asset, &15000);
+    assert_eq!(ctx.contract.try_withdraw_collateral(&1, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.borrow(&1, &ctx.borrower);
+    assert_eq!(ctx.contract.try_withdraw_collateral(&1, &ctx.collateral_asset, &0), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_withdraw_collateral(&1, &ctx.collateral_asset, &(COLLATERAL_AMOUNT + 1)), Err(Ok(Error::InvalidAmount.into())));
+
+    ctx.contract.new_loan(&2, &ctx.loan_request(Vec::new(&ctx.env)));
+    ctx.contract.lend(&2, &ctx.lender);
+    assert_eq!(ctx.contract.try_set_withdrawal_limit(&2, &vec![&ctx.env, oracle_asset.clone()], &oracle_asset, &15000), Err(Ok(Error::InvalidCollateral.into())));
+}
+
+#[test]
+fn test_liquidate() {
+    let ctx = TestContext::new();
+
+    let condition = SeizeCondition::CollateralRatio(ctx.oracle_asset("XLM"), ctx.oracle_asset("USDC"), 12000);
+    let liquidation = Liquidation::Oracle(LiquidationTerms {
+        collateral_assets: vec![&ctx.env, ctx.oracle_asset("XLM")],
+        loan_asset: ctx.oracle_asset("USDC"),
+        bonus: 500,
+        return_surplus: false
+    });
+
+    for loan_key in [1, 2] {
+        ctx.contract.new_loan(&loan_key, &Loan {
+            liquidation: liquidation.clone(),
+            ..ctx.loan_request(ctx.collateral(vec![&ctx.env, condition.clone()]))
+        });
+        ctx.contract.lend(&loan_key, &ctx.lender);
+    }
+
+    ctx.set_price("XLM", PRICE_UNIT / 2);
+    ctx.set_price("USDC", PRICE_UNIT);
+
+    // 400 of debt is covered by 800 of collateral plus 5% bonus
+    ctx.contract.liquidate(&1, &ctx.lender, &ctx.collateral_asset, &(400 * UNIT));
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.status, LoanStatus::InProgress);
+    assert_eq!(loan.collateral.first().unwrap().amount, COLLATERAL_AMOUNT - 840 * UNIT);
+    assert_eq!(loan.accrued_interest, 0);
+    assert_eq!(loan.loan_amount, LOAN_AMOUNT + DAILY_INTEREST - 400 * UNIT);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), 840 * UNIT);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "collateral_liquidated"),).into_val(&ctx.env));
+    assert_eq!(<(u64, Address, Address, i128, i128)>::from_val(&ctx.env, &data), (1, ctx.lender.clone(), ctx.collateral_asset.clone(), 840 * UNIT, 400 * UNIT));
+
+    // the remaining collateral covers only part of the requested amount
+    ctx.contract.liquidate(&1, &ctx.lender, &ctx.collateral_asset, &loan.loan_amount);
+
+    let covered = 1_160 * UNIT / 2 * 10000 / 10500;
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.status, LoanStatus::InProgress);
+    assert_eq!(loan.collateral.first().unwrap().amount, 0);
+    assert_eq!(loan.loan_amount, LOAN_AMOUNT + DAILY_INTEREST - 400 * UNIT - covered);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+
+    // the loan is closed once the debt is covered, the rest of the collateral is returned
+    ctx.set_price("XLM", PRICE_UNIT * 6 / 10);
+    ctx.contract.liquidate(&2, &ctx.lender, &ctx.collateral_asset, &(LOAN_AMOUNT + DAILY_INTEREST));
+
+    let liquidated = 1_001 * UNIT * 10500 / 6000;
+    assert_eq!(ctx.contract.try_get_loan(&2).err(), Some(Ok(Error::LoanNotExist.into())));
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT + liquidated);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), COLLATERAL_AMOUNT - liquidated);
+}
+
+#[test]
+fn test_liquidate_errors() {
+    let ctx = TestContext::new();
+
+    let terms = LiquidationTerms {
+        collateral_assets: vec![&ctx.env, ctx.oracle_asset("XLM")],
+        loan_asset: ctx.oracle_asset("USDC"),
+        bonus: 500,
+        return_surplus: false
+    };
+
+    // terms must price every collateral asset and bonus is limited
+    let condition = SeizeCondition::CollateralRatio(ctx.oracle_asset("XLM"), ctx.oracle_asset("USDC"), 15000);
+    let loan = |terms: LiquidationTerms| Loan {
+        liquidation: Liquidation::Oracle(terms),
+        ..ctx.loan_request(ctx.collateral(vec![&ctx.env, SeizeCondition::LoanDefault, condition.clone()]))
+    };
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(LiquidationTerms { collateral_assets: Vec::new(&ctx.env), ..terms.clone() })), Err(Ok(Error::InvalidCollateral.into())));
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(LiquidationTerms { bonus: MAX_LIQUIDATION_BONUS + 1, ..terms.clone() })), Err(Ok(Error::InvalidCollateral.into())));
+
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &loan(terms));
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.lend(&1, &ctx.lender);
+    ctx.set_price("XLM", PRICE_UNIT);
+    ctx.set_price("USDC", PRICE_UNIT);
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.collateral_asset, &UNIT), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    // defaulted loans are left to seize
+    ctx.set_timestamp(NOW + 31 * DAY);
+    ctx.set_price("XLM", PRICE_UNIT);
+    ctx.set_price("USDC", PRICE_UNIT);
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.collateral_asset, &UNIT), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    ctx.set_price("XLM", PRICE_UNIT / 2);
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.collateral_asset, &0), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.collateral_asset, &(2 * LOAN_AMOUNT)), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.loan_asset, &UNIT), Err(Ok(Error::InvalidCollateral.into())));
+
+    // loans without liquidation terms can only be seized
+    ctx.in_progress_loan(2);
+    assert_eq!(ctx.contract.try_liquidate(&2, &ctx.lender, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LiquidationDisabled.into())));
+}
+
+#[test]
+fn test_liquidate_by_keeper() {
+    let ctx = TestContext::new();
+
+    let keeper = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&keeper, &INITIAL_BALANCE);
+
+    ctx.contract.new_loan(&1, &Loan {
+        liquidation: Liquidation::Oracle(LiquidationTerms {
+            collateral_assets: vec![&ctx.env, ctx.oracle_asset("XLM")],
+            loan_asset: ctx.oracle_asset("USDC"),
+            bonus: 1000,
+            return_surplus: false
+        }),
+        ..ctx.loan_request(ctx.collateral(vec![&ctx.env, SeizeCondition::CollateralRatio(ctx.oracle_asset("XLM"), ctx.oracle_asset("USDC"), 12000)]))
+    });
+    ctx.contract.lend(&1, &ctx.lender);
+
+    ctx.set_price("XLM", PRICE_UNIT / 2);
+    ctx.set_price("USDC", PRICE_UNIT);
+
+    // the keeper repays 300 of debt to the lender and receives 600 of collateral plus 10% bonus
+    ctx.contract.liquidate(&1, &keeper, &ctx.collateral_asset, &(300 * UNIT));
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &keeper), INITIAL_BALANCE - 300 * UNIT);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &keeper), 660 * UNIT);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), 300 * UNIT);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), 0);
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.collateral.first().unwrap().amount, COLLATERAL_AMOUNT - 660 * UNIT);
+    assert_eq!(loan.loan_amount, LOAN_AMOUNT + DAILY_INTEREST - 300 * UNIT);
+
+    let (_, _, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(<(u64, Address, Address, i128, i128)>::from_val(&ctx.env, &data), (1, keeper.clone(), ctx.collateral_asset.clone(), 660 * UNIT, 300 * UNIT));
+
+    // keepers can't touch healthy loans
+    ctx.set_price("XLM", PRICE_UNIT);
+    assert_eq!(ctx.contract.try_liquidate(&1, &keeper, &ctx.collateral_asset, &UNIT), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    // the lender can still seize the rest of the collateral
+    ctx.set_price("XLM", PRICE_UNIT / 2);
+    ctx.contract.seize(&1);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT - 660 * UNIT);
+}
+
+#[test]
+fn test_seize_returns_surplus() {
+    let ctx = TestContext::new();
+
+    let liquidation = Liquidation::Oracle(LiquidationTerms {
+        collateral_assets: vec![&ctx.env, ctx.oracle_asset("XLM")],
+        loan_asset: ctx.oracle_asset("USDC"),
+        bonus: 500,
+        return_surplus: true
+    });
+
+    for loan_key in [1, 2, 3] {
+        ctx.contract.new_loan(&loan_key, &Loan {
+            liquidation: liquidation.clone(),
+            penalty_interest_rate: 20,
+            ..ctx.loan_request(ctx.loan_default_collateral())
+        });
+        ctx.contract.lend(&loan_key, &ctx.lender);
+    }
+
+    ctx.set_timestamp(NOW + 40 * DAY);
+    ctx.set_price("XLM", PRICE_UNIT / 2);
+    ctx.set_price("USDC", PRICE_UNIT);
+
+    // the lender gets collateral worth principal, interest and penalty interest, the bonus is not applied
+    let debt = LOAN_AMOUNT + ctx.contract.get_interest(&1);
+    ctx.contract.seize(&1);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT.min(2 * debt));
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), 0);
+
+    // collateral worth more than the debt
+    ctx.set_price("XLM", PRICE_UNIT * 2);
+    ctx.contract.seize(&2);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT + debt / 2);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), COLLATERAL_AMOUNT - debt / 2);
+    assert_eq!(ctx.contract.try_get_loan(&2).err(), Some(Ok(Error::LoanNotExist.into())));
+
+    // the lender's share is rounded up, the borrower gets the remainder
+    assert_ne!(debt % 3, 0);
+    ctx.set_price("XLM", PRICE_UNIT * 3);
+    ctx.contract.seize(&3);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT + debt / 2 + debt / 3 + 1);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), 2 * COLLATERAL_AMOUNT - debt / 2 - debt / 3 - 1);
+}
+
+#[test]
+fn test_collateral_auction() {
+    let ctx = TestContext::new();
+
+    let bidder = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&bidder, &INITIAL_BALANCE);
+
+    for loan_key in [1, 2] {
+        ctx.contract.new_loan(&loan_key, &Loan {
+            liquidation: Liquidation::Auction(AuctionTerms {
+                collateral_assets: vec![&ctx.env, ctx.oracle_asset("XLM")],
+                loan_asset: ctx.oracle_asset("USDC"),
+                start_premium: 2000,
+                max_discount: 5000,
+                duration: DAY
+            }),
+            ..ctx.loan_request(ctx.loan_default_collateral())
+        });
+        ctx.contract.lend(&loan_key, &ctx.lender);
+    }
+
+    let timestamp = NOW + 31 * DAY;
+    ctx.set_timestamp(timestamp);
+    ctx.set_price("XLM", PRICE_UNIT);
+    ctx.set_price("USDC", PRICE_UNIT);
+
+    ctx.env.deployer().upload_contract_wasm(position_token_contract::WASM);
+    ctx.contract.tokenize_position(&1, &ctx.lender);
+    ctx.contract.propose_amendment(&1, &ctx.borrower, &60, &DAILY_INTEREST_RATE, &UNIT);
+
+    // the auction starts 20% above the collateral value and ends 50% below it
+    let debt = LOAN_AMOUNT + ctx.contract.get_interest(&1);
+    ctx.contract.seize(&1);
+
+    let auction = ctx.contract.get_auction(&1);
+    assert_eq!((auction.start_price, auction.end_price), (2_400 * UNIT, 1_000 * UNIT));
+    assert_eq!((auction.start_timestamp, auction.end_timestamp), (timestamp, timestamp + DAY));
+    assert_eq!(ctx.contract.get_loan(&1).status, LoanStatus::Auction);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), 0);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "auction_started"),).into_val(&ctx.env));
+    assert_eq!(<(u64, i128, i128)>::from_val(&ctx.env, &data), (1, 2_400 * UNIT, 1_000 * UNIT));
+
+    // the debt is fixed during the auction, the proposed amendment fee is returned
+    assert_eq!(ctx.contract.try_repay(&1, &ctx.borrower), Err(Ok(Error::LoanNotInProgress.into())));
+    assert_eq!(ctx.contract.try_accept_amendment(&1, &ctx.lender), Err(Ok(Error::AmendmentNotExist.into())));
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &ctx.borrower, &60, &DAILY_INTEREST_RATE, &0), Err(Ok(Error::LoanNotInProgress.into())));
+    assert_eq!(ctx.contract.try_transfer_position_by_token(&1, &ctx.lender, &bidder), Err(Ok(Error::LoanNotInProgress.into())));
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.borrower), UNIT);
+
+    ctx.set_timestamp(timestamp + DAY / 2);
+    assert_eq!(ctx.contract.get_auction_price(&1), 1_700 * UNIT);
+
+    ctx.contract.bid(&1, &bidder);
+
+    assert_eq!(ctx.balance(&ctx.loan_asset, &bidder), INITIAL_BALANCE - 1_700 * UNIT);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &bidder), COLLATERAL_AMOUNT);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), debt);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.borrower), UNIT + 1_700 * UNIT - debt);
+    assert_eq!(ctx.contract.try_get_loan(&1).err(), Some(Ok(Error::LoanNotExist.into())));
+    assert_eq!(ctx.contract.try_get_auction(&1).err(), Some(Ok(Error::AuctionNotExist.into())));
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "auction_settled"),).into_val(&ctx.env));
+    assert_eq!(<(u64, Address, i128)>::from_val(&ctx.env, &data), (1, bidder.clone(), 1_700 * UNIT));
+
+    // the price stays at the end price, bids below the debt go to lender only
+    ctx.set_price("XLM", PRICE_UNIT / 4);
+    ctx.set_price("USDC", PRICE_UNIT);
+    ctx.contract.seize(&2);
+
+    ctx.set_timestamp(timestamp + 3 * DAY);
+    assert_eq!(ctx.contract.get_auction_price(&2), 250 * UNIT);
+
+    ctx.contract.bid(&2, &bidder);
+
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), debt + 250 * UNIT);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.borrower), UNIT + 1_700 * UNIT - debt);
+}
+
+#[test]
+fn test_collateral_auction_without_bid() {
+    let ctx = TestContext::new();
+
+    ctx.contract.new_loan(&1, &Loan {
+        liquidation: Liquidation::Auction(AuctionTerms {
+            collateral_assets: vec![&ctx.env, ctx.oracle_asset("XLM")],
+            loan_asset: ctx.oracle_asset("USDC"),
+            start_premium: 0,
+            max_discount: 5000,
+            duration: DAY
+        }),
+        ..ctx.loan_request(ctx.loan_default_collateral())
+    });
+    ctx.contract.lend(&1, &ctx.lender);
+
+    let timestamp = NOW + 31 * DAY;
+    ctx.set_timestamp(timestamp);
+    ctx.set_price("XLM", PRICE_UNIT);
+    ctx.set_price("USDC", PRICE_UNIT);
+    ctx.contract.seize(&1);
+
+    // the collateral stays up for auction until the end price is reached
+    ctx.set_timestamp(timestamp + DAY - 1);
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    // nobody bid, the lender takes the collateral
+    ctx.set_timestamp(timestamp + DAY);
+    ctx.contract.seize(&1);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.borrower), 0);
+    assert_eq!(ctx.contract.try_get_loan(&1).err(), Some(Ok(Error::LoanNotExist.into())));
+    assert_eq!(ctx.contract.try_get_auction(&1).err(), Some(Ok(Error::AuctionNotExist.into())));
+
+    let (_, topics, _) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "collateral_seized"),).into_val(&ctx.env));
+}
+
+#[test]
+fn test_collateral_auction_errors() {
+    let ctx = TestContext::new();
+
+    let oracle_asset = ctx.oracle_asset("XLM");
+
+    let terms = AuctionTerms {
+        collateral_assets: vec![&ctx.env, oracle_asset.clone()],
+        loan_asset: oracle_asset,
+        start_premium: 0,
+        max_discount: 5000,
+        duration: DAY
+    };
+
+    let loan = |terms: AuctionTerms| Loan {
+        liquidation: Liquidation::Auction(terms),
+        ..ctx.loan_request(ctx.loan_default_collateral())
+    };
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(AuctionTerms { start_premium: MAX_AUCTION_PREMIUM + 1, ..terms.clone() })), Err(Ok(Error::InvalidCollateral.into())));
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(AuctionTerms { start_premium: u32::MAX, ..terms.clone() })), Err(Ok(Error::InvalidCollateral.into())));
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(AuctionTerms { max_discount: 10001, ..terms.clone() })), Err(Ok(Error::InvalidCollateral.into())));
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(AuctionTerms { duration: 0, ..terms.clone() })), Err(Ok(Error::InvalidCollateral.into())));
+
+    assert_eq!(ctx.contract.try_bid(&1, &ctx.lender), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &loan(terms));
+    ctx.contract.lend(&1, &ctx.lender);
+    assert_eq!(ctx.contract.try_bid(&1, &ctx.lender), Err(Ok(Error::AuctionNotExist.into())));
+    assert_eq!(ctx.contract.try_get_auction_price(&1), Err(Ok(Error::AuctionNotExist.into())));
+
+    // auctions replace partial liquidation
+    assert_eq!(ctx.contract.try_liquidate(&1, &ctx.lender, &ctx.collateral_asset, &UNIT), Err(Ok(Error::LiquidationDisabled.into())));
+}
+
+#[test]
+fn test_installments() {
+    let ctx = TestContext::new();
+
+    let schedule = InstallmentSchedule { count: 3, period: 10, principal: 0 };
+    let collateral = ctx.collateral(vec![&ctx.env, SeizeCondition::MissedInstallments(2)]);
+
+    ctx.contract.new_loan(&1, &Loan {
+        installments: Installments::Schedule(schedule),
+        ..ctx.loan_request(collateral)
+    });
+    ctx.contract.lend(&1, &ctx.lender);
+
+    // the principal is split in equal parts rounded up
+    let installment = (LOAN_AMOUNT + 2) / 3;
+
+    assert_eq!(ctx.contract.get_installments(&1), InstallmentStatus {
+        paid: 0,
+        missed: 0,
+        next_due_timestamp: NOW + 10 * DAY,
+        next_due_amount: installment + DAILY_INTEREST
+    });
+
+    // one missed installment is not enough to seize
+    ctx.set_timestamp(NOW + 10 * DAY);
+    assert_eq!(ctx.contract.get_installments(&1), InstallmentStatus {
+        paid: 0,
+        missed: 1,
+        next_due_timestamp: NOW + 20 * DAY,
+        next_due_amount: 2 * installment - 1 + 11 * DAILY_INTEREST
+    });
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    ctx.contract.repay_partial(&1, &ctx.borrower, &(installment + 11 * DAILY_INTEREST));
+
+    let status = ctx.contract.get_installments(&1);
+    assert_eq!((status.paid, status.missed), (1, 0));
+    assert_eq!(status.next_due_amount, installment - 1);
+
+    // the collateral can be seized after two missed installments
+    ctx.set_timestamp(NOW + 30 * DAY);
+    assert_eq!(ctx.contract.get_seize_status(&1).first().unwrap().conditions.first().unwrap().state, SeizeConditionState::Satisfied);
+    ctx.contract.seize(&1);
+
+    assert_eq!(ctx.claimable(&ctx.collateral_asset, &ctx.lender), COLLATERAL_AMOUNT);
+}
+
+#[test]
+fn test_installments_errors() {
+    let ctx = TestContext::new();
+
+    let loan = |count: u32, period: u32, condition: SeizeCondition| Loan {
+        installments: Installments::Schedule(InstallmentSchedule { count, period, principal: 0 }),
+        ..ctx.loan_request(ctx.collateral(vec![&ctx.env, condition]))
+    };
+
+    // the schedule has to fit in the loan term
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(4, 10, SeizeCondition::LoanDefault)), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(0, 10, SeizeCondition::LoanDefault)), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(3, 10, SeizeCondition::MissedInstallments(0))), Err(Ok(Error::InvalidCollateral.into())));
+
+    // more missed installments than scheduled can never be reached
+    assert_eq!(ctx.contract.try_new_loan(&1, &loan(3, 10, SeizeCondition::MissedInstallments(4))), Err(Ok(Error::InvalidCollateral.into())));
+
+    // a bullet loan has no installments to miss
+    let missed = SeizeCondition::MissedInstallments(1);
+    assert_eq!(ctx.contract.try_new_loan(&1, &ctx.loan_request(ctx.collateral(vec![&ctx.env, missed.clone()]))), Err(Ok(Error::InvalidCollateral.into())));
+    let nested = SeizeCondition::Or(vec![&ctx.env, SeizeCondition::LoanDefault, missed]);
+    assert_eq!(ctx.contract.try_new_loan(&1, &ctx.loan_request(ctx.collateral(vec![&ctx.env, nested]))), Err(Ok(Error::InvalidCollateral.into())));
+
+    ctx.contract.new_loan(&1, &loan(3, 10, SeizeCondition::MissedInstallments(1)));
+    assert_eq!(ctx.contract.try_get_installments(&1), Err(Ok(Error::LoanNotInProgress.into())));
+
+    // and in the term set by an amendment or refinancing
+    ctx.contract.lend(&1, &ctx.lender);
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &ctx.borrower, &20, &DAILY_INTEREST_RATE, &0), Err(Ok(Error::InvalidAmount.into())));
+
+    let new_lender = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&new_lender, &INITIAL_BALANCE);
+    assert_eq!(ctx.contract.try_refinance(&1, &new_lender, &20, &DAILY_INTEREST_RATE), Err(Ok(Error::InvalidAmount.into())));
+    ctx.contract.refinance(&1, &new_lender, &30, &DAILY_INTEREST_RATE);
+
+    // bullet loans have no installment schedule
+    ctx.in_progress_loan(2);
+    assert_eq!(ctx.contract.try_get_installments(&2), Err(Ok(Error::InstallmentsDisabled.into())));
+}
+
+#[test]
+fn test_amendment_proposed_by_lender() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+
+    ctx.contract.propose_amendment(&1, &ctx.lender, &60, &(DAILY_INTEREST_RATE * 2), &(10 * UNIT));
+    assert_eq!(ctx.contract.get_amendment(&1).proposer, ctx.lender);
+
+    let (_, topics, _) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "amendment_proposed"),).into_val(&ctx.env));
+
+    // the proposer can't accept its own terms
+    assert_eq!(ctx.contract.try_accept_amendment(&1, &ctx.lender), Err(Ok(Error::NotAuthorized.into())));
+
+    ctx.set_timestamp(NOW + 4 * DAY);
+    ctx.contract.accept_amendment(&1, &ctx.borrower);
+
+    let (_, topics, _) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "loan_amended"),).into_val(&ctx.env));
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.max_loan_term, 60);
+    assert_eq!(loan.daily_interest_rate, DAILY_INTEREST_RATE * 2);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), 10 * UNIT);
+    assert_eq!(ctx.contract.try_get_amendment(&1).err(), Some(Ok(Error::AmendmentNotExist.into())));
+
+    // started days keep the old rate, the following ones are charged the new one
+    assert_eq!(ctx.contract.get_interest(&1), 5 * DAILY_INTEREST);
+
+    ctx.set_timestamp(NOW + 5 * DAY);
+    assert_eq!(ctx.contract.get_interest(&1), 7 * DAILY_INTEREST);
+
+    // no longer in default after the old term
+    ctx.set_timestamp(NOW + 45 * DAY);
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+}
+
+#[test]
+fn test_amendment_proposed_by_borrower() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+
+    // the offered fee is held by the smart contract
+    ctx.contract.propose_amendment(&1, &ctx.borrower, &60, &DAILY_INTEREST_RATE, &(10 * UNIT));
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.borrower), INITIAL_BALANCE + LOAN_AMOUNT - 10 * UNIT);
+
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &ctx.lender, &90, &DAILY_INTEREST_RATE, &0), Err(Ok(Error::AmendmentAlreadyExist.into())));
+
+    // rejected by the lender, the fee is returned
+    ctx.contract.cancel_amendment(&1, &ctx.lender);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.borrower), 10 * UNIT);
+    assert_eq!(ctx.contract.get_loan(&1).max_loan_term, 30);
+
+    let (_, topics, _) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "amendment_canceled"),).into_val(&ctx.env));
+
+    ctx.contract.propose_amendment(&1, &ctx.borrower, &60, &DAILY_INTEREST_RATE, &(5 * UNIT));
+    ctx.contract.accept_amendment(&1, &ctx.lender);
+
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), 5 * UNIT);
+    assert_eq!(ctx.contract.get_loan(&1).max_loan_term, 60);
+
+    // a pending proposal is dropped with the repaid loan
+    ctx.contract.propose_amendment(&1, &ctx.borrower, &90, &DAILY_INTEREST_RATE, &(5 * UNIT));
+    ctx.contract.repay(&1, &ctx.borrower);
+
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.borrower), 15 * UNIT);
+    assert_eq!(ctx.contract.try_get_amendment(&1).err(), Some(Ok(Error::AmendmentNotExist.into())));
+}
+
+#[test]
+fn test_amendment_errors() {
+    let ctx = TestContext::new();
+    let stranger = Address::generate(&ctx.env);
+
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &ctx.lender, &60, &DAILY_INTEREST_RATE, &0), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &ctx.borrower, &60, &DAILY_INTEREST_RATE, &0), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.lend(&1, &ctx.lender);
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &stranger, &60, &DAILY_INTEREST_RATE, &0), Err(Ok(Error::NotAuthorized.into())));
+    assert_eq!(ctx.contract.try_propose_amendment(&1, &ctx.lender, &60, &DAILY_INTEREST_RATE, &-1), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_accept_amendment(&1, &ctx.borrower), Err(Ok(Error::AmendmentNotExist.into())));
+    assert_eq!(ctx.contract.try_cancel_amendment(&1, &ctx.borrower), Err(Ok(Error::AmendmentNotExist.into())));
+
+    ctx.contract.propose_amendment(&1, &ctx.lender, &60, &DAILY_INTEREST_RATE, &0);
+    assert_eq!(ctx.contract.try_accept_amendment(&1, &stranger), Err(Ok(Error::NotAuthorized.into())));
+    assert_eq!(ctx.contract.try_cancel_amendment(&1, &stranger), Err(Ok(Error::NotAuthorized.into())));
+}
+
+#[test]
+fn test_refinance() {
+    let ctx = TestContext::new();
+    let new_lender = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&new_lender, &INITIAL_BALANCE);
+
+    ctx.in_progress_loan(1);
+    ctx.contract.propose_amendment(&1, &ctx.borrower, &60, &DAILY_INTEREST_RATE, &(5 * UNIT));
+
+    ctx.set_timestamp(NOW + 9 * DAY);
+    ctx.contract.refinance(&1, &new_lender, &90, &(DAILY_INTEREST_RATE / 2));
+
+    // the current lender is paid off, the collateral stays locked
+    let payoff = LOAN_AMOUNT + 10 * DAILY_INTEREST;
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), payoff);
+    assert_eq!(ctx.balance(&ctx.loan_asset, &new_lender), INITIAL_BALANCE - payoff);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.contract.address), COLLATERAL_AMOUNT);
+
+    // the pending amendment is dropped
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.borrower), 5 * UNIT);
+    assert_eq!(ctx.contract.try_get_amendment(&1).err(), Some(Ok(Error::AmendmentNotExist.into())));
+
+    let loan = ctx.contract.get_loan(&1);
+    assert_eq!(loan.lender, Some(new_lender.clone()));
+    assert_eq!(loan.loan_amount, payoff);
+    assert_eq!(loan.accrued_interest, 0);
+    assert_eq!(loan.max_loan_term, 90);
+    assert_eq!(loan.daily_interest_rate, DAILY_INTEREST_RATE / 2);
+    assert_eq!(loan.timestamp, NOW + 9 * DAY);
+
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.get_loans(&new_lender), vec![&ctx.env, 1]);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "loan_refinanced"),).into_val(&ctx.env));
+    assert_eq!(<(u64, Address)>::from_val(&ctx.env, &data), (1, new_lender.clone()));
+
+    // the term starts over
+    ctx.set_timestamp(NOW + 9 * DAY + 60 * DAY);
+    assert_eq!(ctx.contract.try_seize(&1), Err(Ok(Error::CollateralNotSeizable.into())));
+
+    ctx.contract.repay(&1, &ctx.borrower);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &new_lender), payoff + payoff * 61 * (DAILY_INTEREST_RATE / 2) as i128 / 10000);
+}
+
+#[test]
+fn test_refinance_errors() {
+    let ctx = TestContext::new();
+    let new_lender = Address::generate(&ctx.env);
+
+    assert_eq!(ctx.contract.try_refinance(&1, &new_lender, &30, &DAILY_INTEREST_RATE), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_request(ctx.loan_default_collateral()));
+    assert_eq!(ctx.contract.try_refinance(&1, &new_lender, &30, &DAILY_INTEREST_RATE), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.lend(&1, &ctx.lender);
+    assert_eq!(ctx.contract.try_refinance(&1, &ctx.lender, &30, &DAILY_INTEREST_RATE), Err(Ok(Error::InvalidLender.into())));
+    assert_eq!(ctx.contract.try_refinance(&1, &ctx.borrower, &30, &DAILY_INTEREST_RATE), Err(Ok(Error::InvalidLender.into())));
+}
+
+#[test]
+fn test_transfer_position() {
+    let ctx = TestContext::new();
+    let buyer = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&buyer, &INITIAL_BALANCE);
+
+    ctx.in_progress_loan(1);
+    ctx.contract.propose_amendment(&1, &ctx.lender, &60, &DAILY_INTEREST_RATE, &0);
+    ctx.contract.set_withdrawal_limit(&1, &vec![&ctx.env, ctx.oracle_asset("XLM")], &ctx.oracle_asset("USDC"), &15000);
+
+    ctx.contract.transfer_position(&1, &ctx.lender, &buyer, &Some(ctx.loan_asset.clone()), &(900 * UNIT));
+
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), 900 * UNIT);
+    assert_eq!(ctx.balance(&ctx.loan_asset, &buyer), INITIAL_BALANCE - 900 * UNIT);
+    assert_eq!(ctx.contract.get_loan(&1).lender, Some(buyer.clone()));
+    assert_eq!(ctx.contract.try_get_amendment(&1).err(), Some(Ok(Error::AmendmentNotExist.into())));
+    assert_eq!(ctx.contract.try_get_withdrawal_limit(&1).err(), Some(Ok(Error::CollateralNotWithdrawable.into())));
+
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.get_loans(&buyer), vec![&ctx.env, 1]);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "position_transferred"),).into_val(&ctx.env));
+    assert_eq!(<(u64, Address, Address)>::from_val(&ctx.env, &data), (1, ctx.lender.clone(), buyer.clone()));
+
+    // a position given away still has to be accepted
+    let recipient = Address::generate(&ctx.env);
+    ctx.contract.transfer_position(&1, &buyer, &recipient, &None, &0);
+    assert!(ctx.env.auths().iter().any(|(address, _)| *address == recipient));
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &buyer), 0);
+
+    // repayments go to the new lender
+    ctx.contract.repay(&1, &ctx.borrower);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &recipient), LOAN_AMOUNT + DAILY_INTEREST);
+}
+
+#[test]
+fn test_transfer_position_errors() {
+    let ctx = TestContext::new();
+    let buyer = Address::generate(&ctx.env);
+
+    assert_eq!(ctx.contract.try_transfer_position(&1, &ctx.lender, &buyer, &None, &0), Err(Ok(Error::LoanNotExist.into())));
+
+    ctx.contract.new_loan(&1, &ctx.loan_offer(ctx.loan_default_collateral()));
+    assert_eq!(ctx.contract.try_transfer_position(&1, &ctx.lender, &buyer, &None, &0), Err(Ok(Error::LoanNotInProgress.into())));
+
+    ctx.contract.borrow(&1, &ctx.borrower);
+    assert_eq!(ctx.contract.try_transfer_position(&1, &buyer, &ctx.lender, &None, &0), Err(Ok(Error::NotAuthorized.into())));
+    assert_eq!(ctx.contract.try_transfer_position(&1, &ctx.lender, &buyer, &None, &-1), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_transfer_position(&1, &ctx.lender, &buyer, &None, &UNIT), Err(Ok(Error::InvalidAmount.into())));
+    assert_eq!(ctx.contract.try_transfer_position(&1, &ctx.lender, &ctx.borrower, &None, &0), Err(Ok(Error::InvalidLender.into())));
+    assert_eq!(ctx.contract.try_transfer_position(&1, &ctx.lender, &ctx.lender, &None, &0), Err(Ok(Error::InvalidLender.into())));
+}
+
+#[test]
+fn test_withdraw() {
+    let ctx = TestContext::new();
+
+    ctx.in_progress_loan(1);
+    ctx.contract.repay(&1, &ctx.borrower);
+
+    ctx.contract.withdraw(&ctx.lender, &ctx.loan_asset);
+    assert_eq!(ctx.balance(&ctx.loan_asset, &ctx.lender), INITIAL_BALANCE + DAILY_INTEREST);
+    assert_eq!(ctx.claimable(&ctx.loan_asset, &ctx.lender), 0);
+
+    ctx.contract.withdraw(&ctx.borrower, &ctx.collateral_asset);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.borrower), INITIAL_BALANCE);
+    assert_eq!(ctx.balance(&ctx.collateral_asset, &ctx.contract.address), 0);
+
+    // nothing left to withdraw
+    assert_eq!(ctx.contract.try_withdraw(&ctx.lender, &ctx.loan_asset), Err(Ok(Error::NotAuthorized.into())));
+}
+
+#[test]
+fn test_get_balances() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.get_balances(&ctx.lender).len(), 0);
+
+    ctx.in_progress_loan(1);
+    ctx.in_progress_loan(2);
+    ctx.contract.repay(&1, &ctx.borrower);
+    ctx.contract.repay(&2, &ctx.borrower);
+
+    let balances = ctx.contract.get_balances(&ctx.lender);
+    assert_eq!(balances.len(), 1);
+    assert_eq!(balances.get(ctx.loan_asset.clone()), Some(2 * (LOAN_AMOUNT + DAILY_INTEREST)));
+
+    let balances = ctx.contract.get_balances(&ctx.borrower);
+    assert_eq!(balances.get(ctx.collateral_asset.clone()), Some(2 * COLLATERAL_AMOUNT));
+}
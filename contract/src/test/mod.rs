@@ -0,0 +1,32 @@
+#![cfg(test)]
+
+use soroban_sdk::{Address, Env, Vec};
+use crate::types::*;
+
+mod oracle;
+mod loan;
+mod position;
+
+// uncollateralized interest free loan waiting for a lender, tests override the fields they need
+fn loan_fixture(env: &Env, loan_asset: &Address, loan_amount: i128) -> Loan {
+    Loan {
+        borrower: None,
+        lender: None,
+        collateral: Vec::new(env),
+        liquidation: Liquidation::Disabled,
+        status: LoanStatus::WaitingForLender,
+        loan_asset: loan_asset.clone(),
+        loan_amount,
+        daily_interest_rate: 0,
+        max_loan_term: 30,
+        grace_period: 0,
+        penalty_interest_rate: 0,
+        interest_model: InterestModel::DailySimple,
+        floating_rate: FloatingRate::Disabled,
+        installments: Installments::Disabled,
+        timestamp: 0,
+        interest_timestamp: 0,
+        accrued_interest: 0,
+        uncompounded_interest: 0
+    }
+}
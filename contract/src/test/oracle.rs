@@ -0,0 +1,456 @@
+use soroban_sdk::{testutils::{Address as _, Ledger}, vec, Address, Env, Symbol, Vec};
+use reflector_mock::{Asset, ReflectorMock, ReflectorMockClient};
+use crate::types::*;
+use super::loan_fixture;
+
+const NOW: u64 = 1_000_000;
+const PRICE_UNIT: i128 = 100_000_000_000_000;
+
+struct Setup {
+    env: Env,
+    oracle: ReflectorMockClient<'static>,
+    loan: Loan
+}
+
+impl Setup {
+    fn new() -> Self {
+        let env = Env::default();
+        env.ledger().set_timestamp(NOW);
+
+        let oracle_address = env.register_contract(None, ReflectorMock);
+        let oracle = ReflectorMockClient::new(&env, &oracle_address);
+
+        let loan = Loan {
+            status: LoanStatus::InProgress,
+            timestamp: NOW,
+            interest_timestamp: NOW,
+            ..loan_fixture(&env, &Address::generate(&env), 1000)
+        };
+
+        Setup { env, oracle, loan }
+    }
+
+    fn oracle_asset(&self, symbol: &str) -> OracleAsset {
+        OracleAsset {
+            oracle_contract: self.oracle.address.clone(),
+            asset_contract: None,
+            oracle_symbol: Some(Symbol::new(&self.env, symbol)),
+            max_age: 0
+        }
+    }
+
+    fn set_price(&self, symbol: &str, price: i128) {
+        self.oracle.set_price(&Asset::Other(Symbol::new(&self.env, symbol)), &price, &NOW);
+    }
+
+    // evaluates the condition as part of a single collateral asset
+    fn eval(&self, condition: &SeizeCondition) -> bool {
+        let collateral = Collateral {
+            asset_contract: Address::generate(&self.env),
+            amount: 1000,
+            seize_conditions: vec![&self.env, condition.clone()]
+        };
+
+        condition.check(&self.env, &self.loan, &collateral)
+    }
+
+    // checks "amount_a XLM ? amount_b USDC" with XLM priced at 2 and USDC at 1
+    fn check(&self, amount_a: i128, amount_b: i128, comparator: Comparator) -> bool {
+        self.set_price("XLM", 2 * PRICE_UNIT);
+        self.set_price("USDC", PRICE_UNIT);
+
+        let condition = SeizeCondition::ReflectorOracle(
+            self.oracle_asset("XLM"),
+            amount_a,
+            self.oracle_asset("USDC"),
+            amount_b,
+            comparator
+        );
+
+        self.eval(&condition)
+    }
+}
+
+#[test]
+fn test_reflector_oracle_equal_values() {
+    let setup = Setup::new();
+
+    assert!(!setup.check(100, 200, Comparator::Greater));
+    assert!(setup.check(100, 200, Comparator::GreaterOrEqual));
+    assert!(!setup.check(100, 200, Comparator::Less));
+    assert!(setup.check(100, 200, Comparator::LessOrEqual));
+}
+
+#[test]
+fn test_reflector_oracle_value_above() {
+    let setup = Setup::new();
+
+    // 100 XLM = 200 USDC > 199 USDC
+    assert!(setup.check(100, 199, Comparator::Greater));
+    assert!(setup.check(100, 199, Comparator::GreaterOrEqual));
+    assert!(!setup.check(100, 199, Comparator::Less));
+    assert!(!setup.check(100, 199, Comparator::LessOrEqual));
+}
+
+#[test]
+fn test_reflector_oracle_value_below() {
+    let setup = Setup::new();
+
+    // 100 XLM = 200 USDC < 201 USDC, an integer division would truncate the ratio to 0
+    assert!(!setup.check(100, 201, Comparator::Greater));
+    assert!(!setup.check(100, 201, Comparator::GreaterOrEqual));
+    assert!(setup.check(100, 201, Comparator::Less));
+    assert!(setup.check(100, 201, Comparator::LessOrEqual));
+}
+
+#[test]
+fn test_reflector_oracle_ratio_above_one() {
+    let setup = Setup::new();
+
+    // 150 XLM = 300 USDC, an integer division would truncate the ratio to 1
+    assert!(setup.check(150, 200, Comparator::Greater));
+    assert!(!setup.check(150, 200, Comparator::LessOrEqual));
+}
+
+#[test]
+fn test_reflector_oracle_zero_price() {
+    let setup = Setup::new();
+
+    setup.set_price("XLM", 0);
+    setup.set_price("USDC", PRICE_UNIT);
+
+    let condition = SeizeCondition::ReflectorOracle(
+        setup.oracle_asset("XLM"),
+        100,
+        setup.oracle_asset("USDC"),
+        200,
+        Comparator::Less
+    );
+
+    // a crashed price values the asset at 0
+    assert!(setup.eval(&condition));
+
+    let condition = SeizeCondition::ReflectorOracle(
+        setup.oracle_asset("XLM"),
+        100,
+        setup.oracle_asset("USDC"),
+        200,
+        Comparator::Greater
+    );
+
+    assert!(!setup.eval(&condition));
+}
+
+#[test]
+fn test_collateral_ratio_zero_price() {
+    let setup = Setup::new();
+    let condition = SeizeCondition::CollateralRatio(setup.oracle_asset("XLM"), setup.oracle_asset("USDC"), 15000);
+
+    // worthless collateral is below the ratio
+    setup.set_price("XLM", 0);
+    setup.set_price("USDC", PRICE_UNIT);
+    assert!(setup.eval(&condition));
+
+    // debt can't be valued without the loan asset price
+    setup.set_price("XLM", PRICE_UNIT);
+    setup.set_price("USDC", 0);
+    assert!(!setup.eval(&condition));
+}
+
+#[test]
+fn test_lastprice() {
+    let setup = Setup::new();
+
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 600));
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &(2 * PRICE_UNIT), &(NOW - 300));
+
+    assert_eq!(setup.oracle_asset("XLM").lastprice(&setup.env), 2 * PRICE_UNIT);
+}
+
+#[test]
+fn test_lastprice_stellar_asset() {
+    let setup = Setup::new();
+
+    let asset_contract = Address::generate(&setup.env);
+    setup.oracle.set_price(&Asset::Stellar(asset_contract.clone()), &PRICE_UNIT, &NOW);
+
+    let oracle_asset = OracleAsset {
+        oracle_contract: setup.oracle.address.clone(),
+        asset_contract: Some(asset_contract),
+        oracle_symbol: None,
+        max_age: 0
+    };
+
+    assert_eq!(oracle_asset.lastprice(&setup.env), PRICE_UNIT);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #500)")]
+fn test_lastprice_missing() {
+    let setup = Setup::new();
+
+    setup.oracle_asset("XLM").lastprice(&setup.env);
+}
+
+#[test]
+fn test_lastprice_within_resolution() {
+    let setup = Setup::new();
+
+    // default resolution of 300 seconds + 10 seconds tolerance
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 310));
+
+    assert_eq!(setup.oracle_asset("XLM").lastprice(&setup.env), PRICE_UNIT);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #501)")]
+fn test_lastprice_stale() {
+    let setup = Setup::new();
+
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 311));
+
+    setup.oracle_asset("XLM").lastprice(&setup.env);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #501)")]
+fn test_lastprice_stale_oracle_resolution() {
+    let setup = Setup::new();
+
+    setup.oracle.set_resolution(&60);
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 71));
+
+    setup.oracle_asset("XLM").lastprice(&setup.env);
+}
+
+#[test]
+fn test_lastprice_max_age() {
+    let setup = Setup::new();
+
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 3600));
+
+    let oracle_asset = OracleAsset {
+        max_age: 3600,
+        ..setup.oracle_asset("XLM")
+    };
+    assert_eq!(oracle_asset.lastprice(&setup.env), PRICE_UNIT);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #501)")]
+fn test_lastprice_stale_max_age() {
+    let setup = Setup::new();
+
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 3600));
+
+    let oracle_asset = OracleAsset {
+        max_age: 3599,
+        ..setup.oracle_asset("XLM")
+    };
+    oracle_asset.lastprice(&setup.env);
+}
+
+#[test]
+fn test_lastprice_early_ledger() {
+    let setup = Setup::new();
+
+    // no underflow when the ledger timestamp is below the max price age
+    setup.env.ledger().set_timestamp(100);
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &0);
+
+    assert_eq!(setup.oracle_asset("XLM").lastprice(&setup.env), PRICE_UNIT);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #501)")]
+fn test_stale_price_blocks_seize_condition() {
+    let setup = Setup::new();
+
+    setup.set_price("USDC", PRICE_UNIT);
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 600));
+
+    let condition = SeizeCondition::ReflectorOracle(
+        setup.oracle_asset("XLM"),
+        100,
+        setup.oracle_asset("USDC"),
+        200,
+        Comparator::Less
+    );
+
+    setup.eval(&condition);
+}
+
+#[test]
+fn test_twap_ignores_single_spike() {
+    let setup = Setup::new();
+
+    let xlm = Asset::Other(Symbol::new(&setup.env, "XLM"));
+    for (i, price) in [2, 2, 2].iter().enumerate() {
+        setup.oracle.set_price(&xlm, &(price * PRICE_UNIT), &(NOW - 900 + i as u64 * 300));
+    }
+    setup.oracle.set_price(&xlm, &(PRICE_UNIT / 2), &NOW);
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "USDC")), &PRICE_UNIT, &NOW);
+
+    // seizable when 100 XLM are worth less than 150 USDC
+    let spot = SeizeCondition::ReflectorOracle(
+        setup.oracle_asset("XLM"),
+        100,
+        setup.oracle_asset("USDC"),
+        150,
+        Comparator::Less
+    );
+    let twap = SeizeCondition::ReflectorOracleTwap(
+        setup.oracle_asset("XLM"),
+        100,
+        setup.oracle_asset("USDC"),
+        150,
+        Comparator::Less,
+        4
+    );
+
+    assert!(setup.eval(&spot));
+    assert!(!setup.eval(&twap));
+
+    // the price stays low for the whole window
+    for i in 1..4 {
+        setup.env.ledger().set_timestamp(NOW + i * 300);
+        setup.oracle.set_price(&xlm, &(PRICE_UNIT / 2), &(NOW + i * 300));
+        setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "USDC")), &PRICE_UNIT, &(NOW + i * 300));
+    }
+
+    assert!(setup.eval(&twap));
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #501)")]
+fn test_twap_stale() {
+    let setup = Setup::new();
+
+    setup.oracle.set_price(&Asset::Other(Symbol::new(&setup.env, "XLM")), &PRICE_UNIT, &(NOW - 600));
+
+    setup.oracle_asset("XLM").twap(&setup.env, 2);
+}
+
+#[test]
+fn test_twap_condition_validation() {
+    let setup = Setup::new();
+
+    let twap = |records: u32| SeizeCondition::ReflectorOracleTwap(
+        setup.oracle_asset("XLM"),
+        100,
+        setup.oracle_asset("USDC"),
+        150,
+        Comparator::Less,
+        records
+    );
+
+    assert!(!twap(0).is_valid(&setup.loan));
+    assert!(twap(1).is_valid(&setup.loan));
+    assert!(twap(MAX_TWAP_RECORDS).is_valid(&setup.loan));
+    assert!(!twap(MAX_TWAP_RECORDS + 1).is_valid(&setup.loan));
+}
+
+#[test]
+fn test_condition_expression() {
+    let setup = Setup::new();
+
+    setup.set_price("USDC", PRICE_UNIT);
+
+    // loan defaulted OR (XLM below 0.5 USDC AND BTC below 40000 USDC)
+    let condition = SeizeCondition::Or(vec![
+        &setup.env,
+        SeizeCondition::LoanDefault,
+        SeizeCondition::And(vec![
+            &setup.env,
+            SeizeCondition::ReflectorOracle(setup.oracle_asset("XLM"), 2, setup.oracle_asset("USDC"), 1, Comparator::Less),
+            SeizeCondition::ReflectorOracle(setup.oracle_asset("BTC"), 1, setup.oracle_asset("USDC"), 40_000, Comparator::Less)
+        ])
+    ]);
+
+    let check = |xlm_price: i128, btc_price: i128| {
+        setup.set_price("XLM", xlm_price);
+        setup.set_price("BTC", btc_price);
+
+        setup.eval(&condition)
+    };
+
+    assert!(!check(PRICE_UNIT, 50_000 * PRICE_UNIT));
+    assert!(!check(PRICE_UNIT / 4, 50_000 * PRICE_UNIT));
+    assert!(!check(PRICE_UNIT, 30_000 * PRICE_UNIT));
+    assert!(check(PRICE_UNIT / 4, 30_000 * PRICE_UNIT));
+
+    // defaulted loans are seizable regardless of prices
+    setup.env.ledger().set_timestamp(NOW + 30 * 86400);
+    assert!(check(PRICE_UNIT, 50_000 * PRICE_UNIT));
+}
+
+#[test]
+fn test_condition_not() {
+    let setup = Setup::new();
+
+    setup.set_price("XLM", 2 * PRICE_UNIT);
+    setup.set_price("USDC", PRICE_UNIT);
+
+    let greater = SeizeCondition::ReflectorOracle(setup.oracle_asset("XLM"), 100, setup.oracle_asset("USDC"), 100, Comparator::Greater);
+
+    assert!(setup.eval(&greater));
+    assert!(!setup.eval(&SeizeCondition::Not(vec![&setup.env, greater])));
+}
+
+#[test]
+fn test_seizable_skips_remaining_conditions() {
+    let setup = Setup::new();
+
+    // the loan is defaulted and the oracle has no prices at all
+    setup.env.ledger().set_timestamp(NOW + 30 * 86400);
+
+    let collateral = Collateral {
+        asset_contract: Address::generate(&setup.env),
+        amount: 1000,
+        seize_conditions: vec![
+            &setup.env,
+            SeizeCondition::LoanDefault,
+            SeizeCondition::ReflectorOracle(setup.oracle_asset("XLM"), 100, setup.oracle_asset("USDC"), 100, Comparator::Less)
+        ]
+    };
+
+    assert!(collateral.seizable(&setup.env, &setup.loan));
+}
+
+#[test]
+fn test_condition_expression_validation() {
+    let setup = Setup::new();
+
+    let collateral = |seize_conditions: Vec<SeizeCondition>| Collateral {
+        asset_contract: Address::generate(&setup.env),
+        amount: 1000,
+        seize_conditions
+    };
+
+    // nests the condition `depth - 1` times
+    let nested = |depth: u32| {
+        let mut condition = SeizeCondition::LoanDefault;
+        for _ in 1..depth {
+            condition = SeizeCondition::Not(vec![&setup.env, condition]);
+        }
+        condition
+    };
+
+    assert!(collateral(vec![&setup.env, nested(MAX_SEIZE_CONDITION_DEPTH)]).is_valid(&setup.loan));
+    assert!(!collateral(vec![&setup.env, nested(MAX_SEIZE_CONDITION_DEPTH + 1)]).is_valid(&setup.loan));
+
+    let mut conditions = Vec::new(&setup.env);
+    for _ in 0..MAX_SEIZE_CONDITION_NODES {
+        conditions.push_back(SeizeCondition::LoanDefault);
+    }
+    assert!(collateral(conditions.clone()).is_valid(&setup.loan));
+
+    // the And node itself counts as well
+    assert!(!collateral(vec![&setup.env, SeizeCondition::And(conditions)]).is_valid(&setup.loan));
+
+    assert!(!collateral(Vec::new(&setup.env)).is_valid(&setup.loan));
+    assert!(!collateral(vec![&setup.env, SeizeCondition::And(Vec::new(&setup.env))]).is_valid(&setup.loan));
+    assert!(!collateral(vec![&setup.env, SeizeCondition::Or(Vec::new(&setup.env))]).is_valid(&setup.loan));
+    assert!(!collateral(vec![&setup.env, SeizeCondition::Not(Vec::new(&setup.env))]).is_valid(&setup.loan));
+    assert!(!collateral(vec![&setup.env, SeizeCondition::Not(vec![&setup.env, SeizeCondition::LoanDefault, SeizeCondition::LoanDefault])]).is_valid(&setup.loan));
+}
@@ -0,0 +1,184 @@
+use soroban_sdk::{
+    testutils::{Address as _, Events}, token, vec, Address, BytesN, Env, FromVal, IntoVal, Symbol, Vec
+};
+use crate::contract::{P2PLendingContract, P2PLendingContractClient};
+use crate::position_token_contract;
+use crate::types::*;
+use super::loan_fixture;
+
+// 7 decimals of Stellar assets
+const UNIT: i128 = 10_000_000;
+
+const LOAN_AMOUNT: i128 = 1_000 * UNIT;
+const INITIAL_BALANCE: i128 = 10_000 * UNIT;
+
+struct TestContext {
+    env: Env,
+    contract: P2PLendingContractClient<'static>,
+    borrower: Address,
+    lender: Address,
+    loan_asset: Address
+}
+
+impl TestContext {
+    // in progress uncollateralized loan 1 with the position token wasm uploaded
+    fn new() -> Self {
+        let env = Env::default();
+        // the recipient authorizes the nested call from the token to the lending contract
+        env.mock_all_auths_allowing_non_root_auth();
+
+        let contract_address = env.register_contract(None, P2PLendingContract);
+        let contract = P2PLendingContractClient::new(&env, &contract_address);
+
+        let admin = Address::generate(&env);
+        let loan_asset = env.register_stellar_asset_contract_v2(admin).address();
+
+        let borrower = Address::generate(&env);
+        let lender = Address::generate(&env);
+
+        for user in [&borrower, &lender] {
+            token::StellarAssetClient::new(&env, &loan_asset).mint(user, &INITIAL_BALANCE);
+        }
+
+        contract.new_loan(&1, &Loan {
+            borrower: Some(borrower.clone()),
+            daily_interest_rate: 10,
+            ..loan_fixture(&env, &loan_asset, LOAN_AMOUNT)
+        });
+        contract.lend(&1, &lender);
+
+        let wasm_hash = env.deployer().upload_contract_wasm(position_token_contract::WASM);
+        assert_eq!(wasm_hash, BytesN::from_array(&env, &POSITION_TOKEN_WASM_HASH));
+
+        TestContext { env, contract, borrower, lender, loan_asset }
+    }
+
+    fn tokenize(&self) -> token::Client<'static> {
+        token::Client::new(&self.env, &self.contract.tokenize_position(&1, &self.lender))
+    }
+}
+
+#[test]
+fn test_tokenize_position() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.try_get_position_token(&1).err(), Some(Ok(Error::PositionNotTokenized.into())));
+
+    let position = ctx.tokenize();
+    assert_eq!(ctx.contract.get_position_token(&1), position.address);
+
+    let (_, topics, data) = ctx.env.events().all().last().unwrap();
+    assert_eq!(topics, (Symbol::new(&ctx.env, "position_tokenized"),).into_val(&ctx.env));
+    assert_eq!(<(u64, Address)>::from_val(&ctx.env, &data), (1, position.address.clone()));
+
+    // the token is bound to the loan it was deployed for
+    let client = position_token_contract::Client::new(&ctx.env, &position.address);
+    assert_eq!((client.lending_contract(), client.loan_key()), (ctx.contract.address.clone(), 1));
+    assert_eq!(client.try_initialize(&ctx.lender, &2), Err(Ok(position_token_contract::Error::AlreadyInitialized.into())));
+
+    assert_eq!(position.balance(&ctx.lender), 1);
+    assert_eq!(position.balance(&ctx.borrower), 0);
+    assert_eq!(position.decimals(), 0);
+}
+
+#[test]
+fn test_tokenize_position_errors() {
+    let ctx = TestContext::new();
+
+    assert_eq!(ctx.contract.try_tokenize_position(&2, &ctx.lender), Err(Ok(Error::LoanNotExist.into())));
+    assert_eq!(ctx.contract.try_tokenize_position(&1, &ctx.borrower), Err(Ok(Error::NotAuthorized.into())));
+
+    // positions can only be moved by the linked token
+    let buyer = Address::generate(&ctx.env);
+    assert_eq!(ctx.contract.try_transfer_position_by_token(&1, &ctx.lender, &buyer), Err(Ok(Error::PositionNotTokenized.into())));
+
+    // only the lending contract can initialize a token for its loans
+    let token = ctx.env.register_contract_wasm(None, position_token_contract::WASM);
+    ctx.env.set_auths(&[]);
+    assert!(position_token_contract::Client::new(&ctx.env, &token).try_initialize(&ctx.contract.address, &1).is_err());
+}
+
+#[test]
+fn test_position_token_transfer() {
+    let ctx = TestContext::new();
+    let buyer = Address::generate(&ctx.env);
+
+    let position = ctx.tokenize();
+    position.transfer(&ctx.lender, &buyer, &1);
+
+    // the recipient has to accept the position
+    assert!(ctx.env.auths().iter().any(|(address, _)| *address == buyer));
+
+    assert_eq!(position.balance(&ctx.lender), 0);
+    assert_eq!(position.balance(&buyer), 1);
+    assert_eq!(ctx.contract.get_lender(&1), buyer);
+    assert_eq!(ctx.contract.get_loans(&ctx.lender), Vec::new(&ctx.env));
+    assert_eq!(ctx.contract.get_loans(&buyer), vec![&ctx.env, 1]);
+
+    // the position is indivisible and held by a single address
+    assert_eq!(position.try_transfer(&buyer, &ctx.lender, &2), Err(Ok(position_token_contract::Error::InvalidAmount.into())));
+    assert_eq!(position.try_transfer(&ctx.lender, &buyer, &1), Err(Ok(position_token_contract::Error::InsufficientBalance.into())));
+    assert_eq!(position.try_burn(&buyer, &1), Err(Ok(position_token_contract::Error::NotSupported.into())));
+
+    // repayments go to the holder, the position is gone afterwards
+    ctx.contract.repay(&1, &ctx.borrower);
+
+    assert_eq!(ctx.contract.get_balances(&buyer).get(ctx.loan_asset.clone()), Some(LOAN_AMOUNT + LOAN_AMOUNT * 10 / 10000));
+    assert_eq!(position.balance(&buyer), 0);
+    assert_eq!(ctx.contract.try_get_position_token(&1).err(), Some(Ok(Error::PositionNotTokenized.into())));
+}
+
+#[test]
+fn test_position_token_transfer_from() {
+    let ctx = TestContext::new();
+    let escrow = Address::generate(&ctx.env);
+    let buyer = Address::generate(&ctx.env);
+
+    let position = ctx.tokenize();
+
+    assert_eq!(position.try_transfer_from(&escrow, &ctx.lender, &buyer, &1), Err(Ok(position_token_contract::Error::InsufficientAllowance.into())));
+
+    position.approve(&ctx.lender, &escrow, &1, &(ctx.env.ledger().sequence() + 100));
+    assert_eq!(position.allowance(&ctx.lender, &escrow), 1);
+
+    position.transfer_from(&escrow, &ctx.lender, &buyer, &1);
+
+    assert_eq!(position.allowance(&ctx.lender, &escrow), 0);
+    assert_eq!(position.balance(&buyer), 1);
+    assert_eq!(ctx.contract.get_lender(&1), buyer);
+}
+
+#[test]
+fn test_refinance_unlinks_position_token() {
+    let ctx = TestContext::new();
+    let new_lender = Address::generate(&ctx.env);
+    token::StellarAssetClient::new(&ctx.env, &ctx.loan_asset).mint(&new_lender, &INITIAL_BALANCE);
+
+    let position = ctx.tokenize();
+    ctx.contract.refinance(&1, &new_lender, &30, &10);
+
+    // the token sold by the previous lender can't move the new position
+    assert_eq!(ctx.contract.try_get_position_token(&1).err(), Some(Ok(Error::PositionNotTokenized.into())));
+    assert_eq!(ctx.contract.try_transfer_position_by_token(&1, &new_lender, &ctx.lender), Err(Ok(Error::PositionNotTokenized.into())));
+    assert_eq!(ctx.contract.get_lender(&1), new_lender);
+    assert_eq!(position.balance(&ctx.lender), 0);
+}
+
+#[test]
+fn test_transfer_position_unlinks_position_token() {
+    let ctx = TestContext::new();
+    let buyer = Address::generate(&ctx.env);
+
+    let position = ctx.tokenize();
+    ctx.contract.transfer_position(&1, &ctx.lender, &buyer, &None, &0);
+
+    // the previous lender can't take the position back with the token
+    assert_eq!(ctx.contract.try_get_position_token(&1).err(), Some(Ok(Error::PositionNotTokenized.into())));
+    assert_eq!(ctx.contract.try_transfer_position_by_token(&1, &buyer, &ctx.lender), Err(Ok(Error::PositionNotTokenized.into())));
+    assert_eq!(ctx.contract.get_lender(&1), buyer);
+
+    // the new lender can tokenize the position again
+    assert_eq!(ctx.contract.tokenize_position(&1, &buyer), position.address);
+    position.transfer(&buyer, &ctx.lender, &1);
+    assert_eq!(ctx.contract.get_lender(&1), ctx.lender);
+}
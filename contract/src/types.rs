@@ -1,16 +1,47 @@
 use soroban_sdk::{contracttype, contracterror, Address, Symbol, Vec, Env, panic_with_error, I256};
 use crate::reflector_oracle;
+use crate::contract::{_calculate_interest, _below_collateral_ratio};
 
 pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
 pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
 pub(crate) const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - DAY_IN_LEDGERS;
+pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+pub(crate) const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+// allowed delay of an oracle update on top of the oracle sampling interval
+pub(crate) const ORACLE_TIMESTAMP_TOLERANCE: u64 = 10;
+// one day of 5 minute records
+pub(crate) const MAX_TWAP_RECORDS: u32 = 288;
+pub(crate) const MAX_SEIZE_CONDITION_DEPTH: u32 = 4;
+pub(crate) const MAX_SEIZE_CONDITION_NODES: u32 = 16;
+pub(crate) const MAX_COLLATERAL_ASSETS: u32 = 4;
+// loan keys assigned by the contract start here, caller-chosen keys stay below
+pub(crate) const ASSIGNED_LOAN_KEY_START: u64 = 1 << 32;
+// sha256 of position_token.wasm, it has to be uploaded before positions can be tokenized
+pub(crate) const POSITION_TOKEN_WASM_HASH: [u8; 32] = [
+    25, 22, 161, 177, 135, 45, 178, 66, 18, 122, 214, 98, 29, 147, 238, 95,
+    255, 128, 133, 236, 26, 96, 129, 21, 35, 100, 176, 31, 242, 195, 234, 10
+];
+pub(crate) const MAX_LIQUIDATION_BONUS: u32 = 5000;
+// auctions start at most at twice the collateral value
+pub(crate) const MAX_AUCTION_PREMIUM: u32 = 10000;
+pub(crate) const DAY_IN_SECONDS: u64 = 86400;
+pub(crate) const YEAR_IN_SECONDS: u64 = 365 * DAY_IN_SECONDS;
+// fixed point scale of compound interest factors
+pub(crate) const INTEREST_FACTOR_SCALE: i128 = 1_000_000_000_000_000_000;
+// compound interest factors are capped at a trillion, long overdue loans can't overflow the debt
+pub(crate) const MAX_INTEREST_FACTOR: i128 = INTEREST_FACTOR_SCALE * 1_000_000_000_000;
 
 #[derive(Clone, Debug)]
 #[contracttype]
 pub enum DataKey {
+    LoanCounter,
     Loan(u64),
     Loans(Address),
-    Balances(Address)
+    Balances(Address),
+    Amendment(u64),
+    PositionToken(u64),
+    WithdrawalLimit(u64),
+    Auction(u64)
 }
 
 
@@ -19,13 +50,263 @@ pub enum DataKey {
 pub struct Loan {
     pub borrower: Option<Address>,
     pub lender: Option<Address>,
-    pub collateral: Option<Collateral>,
+    pub collateral: Vec<Collateral>,
+    pub liquidation: Liquidation,
     pub status: LoanStatus,
     pub loan_asset: Address,
     pub loan_amount: i128,
     pub daily_interest_rate: u32,
     pub max_loan_term: u32,
-    pub timestamp: u64
+    pub grace_period: u32, // days after the loan term before the collateral can be seized on default
+    pub penalty_interest_rate: u32, // additional daily interest rate for the days after the loan term
+    pub interest_model: InterestModel,
+    pub floating_rate: FloatingRate,
+    pub installments: Installments,
+    pub timestamp: u64,
+    pub interest_timestamp: u64,
+    pub accrued_interest: i128,
+    pub uncompounded_interest: i128 // part of the accrued interest compounded on the next loan anniversary
+}
+
+// how the daily interest rate is applied to the debt
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum InterestModel {
+    DailySimple, // every started day is charged in full on the principal
+    PerSecondSimple, // charged per second on the principal
+    DailyCompound, // every started day is charged in full on the principal and the accrued interest
+    AnnualCompound // charged per second on the principal and the interest accrued until the last loan anniversary
+}
+
+// daily interest rate of the loan following an oracle fed benchmark,
+// the rate is checkpointed whenever the interest is accrued
+#[derive(Clone, Debug)]
+#[contracttype]
+#[allow(clippy::large_enum_variant)]
+pub enum FloatingRate {
+    Disabled,
+    Oracle(FloatingRateTerms)
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct FloatingRateTerms {
+    pub reference: OracleAsset, // annual rate published as a price, 1 = 100%
+    pub spread: i32, // added to the daily reference rate in basis points
+    pub floor: u32,
+    pub cap: u32
+}
+
+impl FloatingRateTerms {
+    pub fn is_valid(&self) -> bool {
+        self.floor <= self.cap
+    }
+
+    // daily rate in basis points, None if the reference is unavailable
+    pub fn try_rate(&self, env: &Env) -> Option<u32> {
+        let price = self.reference.try_lastprice(env).ok()?.price;
+        let unit = 10i128.checked_pow(self.reference.try_decimals(env).ok()?)?;
+
+        let reference_rate = price.checked_mul(10000)? / unit.checked_mul(365)?;
+
+        Some(reference_rate.saturating_add(self.spread as i128).clamp(self.floor as i128, self.cap as i128) as u32)
+    }
+}
+
+// repayment of the principal in equal parts at the end of each period,
+// interest accrued until the payment is due on top
+#[derive(Clone, Debug)]
+#[contracttype]
+pub enum Installments {
+    Disabled,
+    Schedule(InstallmentSchedule)
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct InstallmentSchedule {
+    pub count: u32,
+    pub period: u32, // days
+    pub principal: i128 // set when the loan starts
+}
+
+impl InstallmentSchedule {
+    pub fn is_valid(&self, max_loan_term: u32) -> bool {
+        self.count > 0 && self.period > 0 && self.count as u64 * self.period as u64 <= max_loan_term as u64
+    }
+
+    // principal to be repaid by the installment, rounded up
+    pub fn due_principal(&self, installment: u32) -> i128 {
+        (self.principal * installment as i128 + self.count as i128 - 1) / self.count as i128
+    }
+
+    // installments covered by the principal repaid so far
+    pub fn paid(&self, loan: &Loan) -> u32 {
+        if self.principal <= 0 {
+            return self.count;
+        }
+
+        let repaid = (self.principal - loan.loan_amount).max(0);
+
+        ((repaid * self.count as i128 / self.principal) as u32).min(self.count)
+    }
+
+    // installments with the due date passed
+    pub fn due(&self, env: &Env, loan: &Loan) -> u32 {
+        let periods = env.ledger().timestamp().saturating_sub(loan.timestamp) / (self.period as u64 * DAY_IN_SECONDS);
+
+        periods.min(self.count as u64) as u32
+    }
+}
+
+// installment schedule state of an in progress loan
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct InstallmentStatus {
+    pub paid: u32,
+    pub missed: u32,
+    pub next_due_timestamp: u64,
+    pub next_due_amount: i128 // principal due by the next due date and the interest accrued so far
+}
+
+impl Loan {
+    // collateral of the loan can be seized if the conditions of any of the assets are met
+    pub fn seizable(&self, env: &Env) -> bool {
+        self.collateral.iter().any(|collateral| collateral.seizable(env, self))
+    }
+
+    // collateral of the loan can be liquidated if the oracle conditions of any of the assets are met
+    pub fn liquidatable(&self, env: &Env) -> bool {
+        self.collateral.iter().any(|collateral| collateral.liquidatable(env, self))
+    }
+
+    // condition states of each collateral asset in the order of the collateral list
+    pub fn seize_status(&self, env: &Env) -> Vec<CollateralSeizeStatus> {
+        let mut statuses = Vec::new(env);
+
+        for collateral in self.collateral.iter() {
+            statuses.push_back(CollateralSeizeStatus {
+                conditions: collateral.status(env, self),
+                asset_contract: collateral.asset_contract
+            });
+        }
+
+        statuses
+    }
+
+    // installments due but not paid, 0 if the loan has no installment schedule
+    pub fn missed_installments(&self, env: &Env) -> u32 {
+        match &self.installments {
+            Installments::Schedule(schedule) => schedule.due(env, self).saturating_sub(schedule.paid(self)),
+            Installments::Disabled => 0
+        }
+    }
+
+    // value of all collateral assets priced with the oracle assets in the order of the collateral list,
+    // None if any of the prices is 0
+    pub fn try_collateral_value(&self, env: &Env, collateral_assets: &Vec<OracleAsset>, prices: &mut Vec<OraclePrice>) -> Result<Option<I256>, Error> {
+        if collateral_assets.len() != self.collateral.len() {
+            return Err(Error::InvalidCollateral);
+        }
+
+        let mut value = I256::from_i32(env, 0);
+        let mut priced = true;
+
+        for (collateral, collateral_asset) in self.collateral.iter().zip(collateral_assets.iter()) {
+            let collateral_price = collateral_asset.try_lastprice(env)?;
+
+            priced &= collateral_price.price != 0;
+            value = value.add(&I256::from_i128(env, collateral_price.price).mul(&I256::from_i128(env, collateral.amount)));
+
+            prices.push_back(collateral_price);
+        }
+
+        Ok(if priced { Some(value) } else { None })
+    }
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+#[allow(clippy::large_enum_variant)]
+pub enum Liquidation {
+    Disabled,
+    Oracle(LiquidationTerms), // collateral can be liquidated partially at oracle prices
+    Auction(AuctionTerms) // seized collateral is sold in a descending price auction
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct LiquidationTerms {
+    pub collateral_assets: Vec<OracleAsset>, // in the order of the collateral list
+    pub loan_asset: OracleAsset,
+    pub bonus: u32, // collateral paid on top of the covered debt in basis points
+    pub return_surplus: bool // on seizure the lender gets only collateral worth the debt, the rest goes to the borrower
+}
+
+impl LiquidationTerms {
+    pub fn is_valid(&self, collateral_assets: u32) -> bool {
+        collateral_assets > 0 && self.collateral_assets.len() == collateral_assets && self.bonus <= MAX_LIQUIDATION_BONUS
+    }
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct AuctionTerms {
+    pub collateral_assets: Vec<OracleAsset>, // in the order of the collateral list
+    pub loan_asset: OracleAsset,
+    pub start_premium: u32, // starting price above the oracle value of the collateral in basis points
+    pub max_discount: u32, // final price below the oracle value of the collateral in basis points
+    pub duration: u64 // seconds from the starting price to the final price
+}
+
+impl AuctionTerms {
+    pub fn is_valid(&self, collateral_assets: u32) -> bool {
+        collateral_assets > 0 && self.collateral_assets.len() == collateral_assets
+            && self.start_premium <= MAX_AUCTION_PREMIUM && self.max_discount <= 10000 && self.duration > 0
+    }
+}
+
+// descending price auction of the whole collateral in loan asset,
+// the price decreases linearly from the start price to the end price and stays there
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct Auction {
+    pub start_price: i128,
+    pub end_price: i128,
+    pub start_timestamp: u64,
+    pub end_timestamp: u64
+}
+
+impl Auction {
+    pub fn price(&self, timestamp: u64) -> i128 {
+        if timestamp >= self.end_timestamp {
+            return self.end_price;
+        }
+
+        let elapsed = timestamp.saturating_sub(self.start_timestamp) as i128;
+        let duration = (self.end_timestamp - self.start_timestamp) as i128;
+
+        self.start_price - (self.start_price - self.end_price) * elapsed / duration
+    }
+}
+
+// min collateral value / debt value in basis points kept on collateral withdrawals
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct WithdrawalLimit {
+    pub collateral_assets: Vec<OracleAsset>, // in the order of the collateral list
+    pub loan_asset: OracleAsset,
+    pub min_ratio: u32
+}
+
+// new loan terms proposed by one of the parties, waiting for the other one
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct LoanAmendment {
+    pub proposer: Address,
+    pub max_loan_term: u32,
+    pub daily_interest_rate: u32,
+    pub fee: i128 // paid by the borrower to the lender on acceptance
 }
 
 #[derive(Clone, Debug)]
@@ -37,16 +318,57 @@ pub struct Collateral {
 }
 
 impl Collateral {
-    pub fn seizable(&self, env: &Env, loan: Loan) -> bool {
-        let mut seizable = false;
+    // loan - the loan the collateral belongs to
+    pub fn is_valid(&self, loan: &Loan) -> bool {
+        if self.seize_conditions.is_empty() || self.amount <= 0 {
+            return false;
+        }
+
+        let mut nodes = 0;
 
         for seize_condition in self.seize_conditions.iter() {
-            if seize_condition.check(env, loan.clone()) {
-                seizable = true;
+            if !seize_condition.is_valid(loan) {
+                return false;
             }
+
+            let (condition_nodes, depth) = seize_condition.size();
+            if depth > MAX_SEIZE_CONDITION_DEPTH {
+                return false;
+            }
+
+            nodes += condition_nodes;
         }
 
-        seizable
+        nodes <= MAX_SEIZE_CONDITION_NODES
+    }
+
+    pub fn seizable(&self, env: &Env, loan: &Loan) -> bool {
+        // any of the conditions, the remaining ones are not evaluated
+        self.seize_conditions.iter().any(|seize_condition| seize_condition.check(env, loan, self))
+    }
+
+    // defaulted loans and missed installments are settled by seizing the collateral
+    pub fn liquidatable(&self, env: &Env, loan: &Loan) -> bool {
+        self.seize_conditions.iter().any(|seize_condition| seize_condition.oracle_based() && seize_condition.check(env, loan, self))
+    }
+
+    // evaluates every condition, oracle failures are reported instead of panicking
+    pub fn status(&self, env: &Env, loan: &Loan) -> Vec<SeizeConditionStatus> {
+        let mut statuses = Vec::new(env);
+
+        for seize_condition in self.seize_conditions.iter() {
+            let mut prices = Vec::new(env);
+
+            let state = match seize_condition.try_check(env, loan, self, &mut prices) {
+                Ok(true) => SeizeConditionState::Satisfied,
+                Ok(false) => SeizeConditionState::NotSatisfied,
+                Err(error) => SeizeConditionState::Unavailable(error as u32)
+            };
+
+            statuses.push_back(SeizeConditionStatus { state, prices });
+        }
+
+        statuses
     }
 }
 
@@ -54,70 +376,306 @@ impl Collateral {
 #[contracttype]
 pub enum SeizeCondition {
     LoanDefault,
-    ReflectorOracle(OracleAsset, i128, OracleAsset, i128, bool) // Asset_A, Amount_A, Asset_B, Amount_B, A>B/A<B
+    ReflectorOracle(OracleAsset, i128, OracleAsset, i128, Comparator), // Asset_A, Amount_A, Asset_B, Amount_B, A ? B
+    CollateralRatio(OracleAsset, OracleAsset, u32), // Collateral_Asset, Loan_Asset, min collateral value / debt value in basis points
+    BasketRatio(Vec<OracleAsset>, OracleAsset, u32), // Collateral_Assets in the order of the collateral list, Loan_Asset, min basket value / debt value in basis points
+    ReflectorOracleTwap(OracleAsset, i128, OracleAsset, i128, Comparator, u32), // Asset_A, Amount_A, Asset_B, Amount_B, A ? B, TWAP records
+    And(Vec<SeizeCondition>), // all of the conditions
+    Or(Vec<SeizeCondition>), // any of the conditions
+    Not(Vec<SeizeCondition>), // negation of a single condition
+    MissedInstallments(u32) // number of installments due but not paid
 }
 
 impl SeizeCondition {
-    pub fn check(&self, env: &Env, loan: Loan) -> bool {
+    pub fn is_valid(&self, loan: &Loan) -> bool {
+        match self {
+            SeizeCondition::ReflectorOracleTwap(_, _, _, _, _, records) => *records > 0 && *records <= MAX_TWAP_RECORDS,
+            SeizeCondition::BasketRatio(basket_assets, _, _) => basket_assets.len() == loan.collateral.len(),
+            // can only be met by installments of the loan schedule
+            SeizeCondition::MissedInstallments(installments) => match &loan.installments {
+                Installments::Schedule(schedule) => *installments > 0 && *installments <= schedule.count,
+                Installments::Disabled => false
+            },
+            SeizeCondition::And(conditions) | SeizeCondition::Or(conditions) => {
+                !conditions.is_empty() && conditions.iter().all(|condition| condition.is_valid(loan))
+            },
+            SeizeCondition::Not(conditions) => {
+                conditions.len() == 1 && conditions.iter().all(|condition| condition.is_valid(loan))
+            },
+            _ => true
+        }
+    }
+
+    // number of nodes and depth of the condition tree
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            SeizeCondition::And(conditions) | SeizeCondition::Or(conditions) | SeizeCondition::Not(conditions) => {
+                let mut nodes = 1;
+                let mut depth = 0;
+
+                for condition in conditions.iter() {
+                    let (condition_nodes, condition_depth) = condition.size();
+                    nodes += condition_nodes;
+                    depth = depth.max(condition_depth);
+                }
+
+                (nodes, depth + 1)
+            },
+            _ => (1, 1)
+        }
+    }
+
+    // condition depends on oracle prices only
+    pub fn oracle_based(&self) -> bool {
+        match self {
+            SeizeCondition::LoanDefault | SeizeCondition::MissedInstallments(_) => false,
+            SeizeCondition::And(conditions) | SeizeCondition::Or(conditions) | SeizeCondition::Not(conditions) => {
+                conditions.iter().all(|condition| condition.oracle_based())
+            },
+            _ => true
+        }
+    }
+
+    // collateral - the collateral asset the condition belongs to
+    pub fn check(&self, env: &Env, loan: &Loan, collateral: &Collateral) -> bool {
+        let mut prices = Vec::new(env);
+
+        self.try_check(env, loan, collateral, &mut prices).unwrap_or_else(|error| panic_with_error!(env, error))
+    }
+
+    // evaluates the condition without panicking on oracle failures, prices used are appended to `prices`
+    pub fn try_check(&self, env: &Env, loan: &Loan, collateral: &Collateral, prices: &mut Vec<OraclePrice>) -> Result<bool, Error> {
         let ledger = env.ledger();
         match self {
             SeizeCondition::LoanDefault => {
                 let loan_duration = ((ledger.timestamp() - loan.timestamp) / 86400) + 1;
-                loan_duration > loan.max_loan_term as u64
+                Ok(loan_duration > loan.max_loan_term as u64 + loan.grace_period as u64)
             },
-            SeizeCondition::ReflectorOracle(asset_a, amount_a, asset_b, amount_b, greater) => {
-                let asset_a_price = asset_a.lastprice(&env);
-                let asset_b_price = asset_b.lastprice(&env);
+            SeizeCondition::MissedInstallments(installments) => Ok(loan.missed_installments(env) >= *installments),
+            SeizeCondition::ReflectorOracle(asset_a, amount_a, asset_b, amount_b, comparator) => {
+                let asset_a_price = asset_a.try_lastprice(env)?;
+                let asset_b_price = asset_b.try_lastprice(env)?;
 
-                if asset_b_price == 0 || *amount_b == 0 {
-                    return false;
-                }
+                prices.push_back(asset_a_price.clone());
+                prices.push_back(asset_b_price.clone());
+
+                Ok(comparator.compare_values(env, asset_a_price.price, *amount_a, asset_b_price.price, *amount_b))
+            },
+            SeizeCondition::ReflectorOracleTwap(asset_a, amount_a, asset_b, amount_b, comparator, records) => {
+                let asset_a_price = asset_a.try_twap(env, *records)?;
+                let asset_b_price = asset_b.try_twap(env, *records)?;
 
-                let asset_a_value = I256::from_i128(env, asset_a_price)
-                                            .mul(&I256::from_i128(env, *amount_a));
+                prices.push_back(asset_a_price.clone());
+                prices.push_back(asset_b_price.clone());
 
-                let asset_b_value = I256::from_i128(env, asset_b_price)
-                                            .mul(&I256::from_i128(env, *amount_b));
+                Ok(comparator.compare_values(env, asset_a_price.price, *amount_a, asset_b_price.price, *amount_b))
+            },
+            SeizeCondition::And(conditions) => {
+                for condition in conditions.iter() {
+                    if !condition.try_check(env, loan, collateral, prices)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            },
+            SeizeCondition::Or(conditions) => {
+                for condition in conditions.iter() {
+                    if condition.try_check(env, loan, collateral, prices)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+            SeizeCondition::Not(conditions) => {
+                for condition in conditions.iter() {
+                    if condition.try_check(env, loan, collateral, prices)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            },
+            SeizeCondition::CollateralRatio(collateral_asset, loan_asset, min_ratio) => {
+                let collateral_price = collateral_asset.try_lastprice(env)?;
+                let loan_asset_price = loan_asset.try_lastprice(env)?;
 
-                let diff = asset_a_value.div(&asset_b_value).to_i128().unwrap_or(0);
+                prices.push_back(collateral_price.clone());
+                prices.push_back(loan_asset_price.clone());
 
-                if *greater {
-                    return diff > 0;
+                // worthless collateral is below any ratio
+                if loan_asset_price.price == 0 {
+                    return Ok(false);
                 }
 
-                diff == 0
+                let collateral_value = I256::from_i128(env, collateral_price.price).mul(&I256::from_i128(env, collateral.amount));
+
+                // outstanding debt including the interest accrued so far
+                let debt = loan.loan_amount + _calculate_interest(env, loan);
+
+                Ok(_below_collateral_ratio(env, &collateral_value, loan_asset_price.price, debt, *min_ratio))
+            },
+            SeizeCondition::BasketRatio(collateral_assets, loan_asset, min_ratio) => {
+                let collateral_value = loan.try_collateral_value(env, collateral_assets, prices)?;
+                let loan_asset_price = loan_asset.try_lastprice(env)?;
+
+                prices.push_back(loan_asset_price.clone());
+
+                let collateral_value = match collateral_value {
+                    Some(collateral_value) if loan_asset_price.price != 0 => collateral_value,
+                    _ => return Ok(false)
+                };
+
+                // outstanding debt including the interest accrued so far
+                let debt = loan.loan_amount + _calculate_interest(env, loan);
+
+                Ok(_below_collateral_ratio(env, &collateral_value, loan_asset_price.price, debt, *min_ratio))
             }
         }
     }
 }
 
+// result of a single seize condition evaluation
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum SeizeConditionState {
+    Satisfied,
+    NotSatisfied,
+    Unavailable(u32) // error code of the failed oracle read
+}
+
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct SeizeConditionStatus {
+    pub state: SeizeConditionState,
+    pub prices: Vec<OraclePrice>
+}
+
+// condition states of a single collateral asset in the order of its seize conditions
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct CollateralSeizeStatus {
+    pub asset_contract: Address,
+    pub conditions: Vec<SeizeConditionStatus>
+}
+
+// price read from an oracle while evaluating a condition
+#[derive(Clone, Debug)]
+#[contracttype]
+pub struct OraclePrice {
+    pub asset: OracleAsset,
+    pub price: i128,
+    pub timestamp: u64, // timestamp of the most recent record
+    pub records: u32 // number of averaged records, 1 - last price
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Comparator {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual
+}
+
+impl Comparator {
+    pub fn compare(&self, a: &I256, b: &I256) -> bool {
+        match self {
+            Comparator::Greater => a > b,
+            Comparator::GreaterOrEqual => a >= b,
+            Comparator::Less => a < b,
+            Comparator::LessOrEqual => a <= b
+        }
+    }
+
+    // compares price_a * amount_a with price_b * amount_b, no division involved
+    pub fn compare_values(&self, env: &Env, price_a: i128, amount_a: i128, price_b: i128, amount_b: i128) -> bool {
+        let value_a = I256::from_i128(env, price_a)
+                                .mul(&I256::from_i128(env, amount_a));
+
+        let value_b = I256::from_i128(env, price_b)
+                                .mul(&I256::from_i128(env, amount_b));
+
+        self.compare(&value_a, &value_b)
+    }
+}
+
 #[derive(Clone, Debug)]
 #[contracttype]
 pub struct OracleAsset {
     pub oracle_contract: Address,
     pub asset_contract: Option<Address>,
-    pub oracle_symbol: Option<Symbol>
+    pub oracle_symbol: Option<Symbol>,
+    pub max_age: u32 // max price age in seconds, 0 - oracle resolution + tolerance
 }
 
 impl OracleAsset {
     pub fn lastprice(&self, env: &Env) -> i128 {
-        let ledger = env.ledger();
+        self.try_lastprice(env).unwrap_or_else(|error| panic_with_error!(env, error)).price
+    }
+
+    pub fn twap(&self, env: &Env, records: u32) -> i128 {
+        self.try_twap(env, records).unwrap_or_else(|error| panic_with_error!(env, error)).price
+    }
+
+    pub fn try_lastprice(&self, env: &Env) -> Result<OraclePrice, Error> {
+        let price_data = self.fresh_price_data(env)?;
+
+        Ok(OraclePrice { asset: self.clone(), price: price_data.price, timestamp: price_data.timestamp, records: 1 })
+    }
+
+    pub fn try_twap(&self, env: &Env, records: u32) -> Result<OraclePrice, Error> {
+        // the most recent record has to be fresh as well
+        let price_data = self.fresh_price_data(env)?;
+
+        let reflector_contract = reflector_oracle::Client::new(env, &self.oracle_contract);
+
+        let price = match reflector_contract.try_twap(&self.reflector_asset(), &records) {
+            Ok(Ok(Some(price))) => price,
+            _ => return Err(Error::OracleError)
+        };
+
+        Ok(OraclePrice { asset: self.clone(), price, timestamp: price_data.timestamp, records })
+    }
 
-        let reflector_contract = reflector_oracle::Client::new(&env, &self.oracle_contract);
+    pub fn try_decimals(&self, env: &Env) -> Result<u32, Error> {
+        let reflector_contract = reflector_oracle::Client::new(env, &self.oracle_contract);
 
-        let oracle_asset = match self.oracle_symbol.clone() {
+        match reflector_contract.try_decimals() {
+            Ok(Ok(decimals)) => Ok(decimals),
+            _ => Err(Error::OracleError)
+        }
+    }
+
+    fn reflector_asset(&self) -> reflector_oracle::Asset {
+        match self.oracle_symbol.clone() {
             Some(symbol) => reflector_oracle::Asset::Other(symbol),
             None => reflector_oracle::Asset::Stellar(self.asset_contract.clone().unwrap())
+        }
+    }
+
+    fn fresh_price_data(&self, env: &Env) -> Result<reflector_oracle::PriceData, Error> {
+        let ledger = env.ledger();
+
+        let reflector_contract = reflector_oracle::Client::new(env, &self.oracle_contract);
+
+        let asset_price_data = match reflector_contract.try_lastprice(&self.reflector_asset()) {
+            Ok(Ok(Some(price_data))) => price_data,
+            _ => return Err(Error::OracleError)
+        };
+
+        // checking if the price is not stale
+        let max_age = match self.max_age {
+            0 => match reflector_contract.try_resolution() {
+                Ok(Ok(resolution)) => resolution as u64 + ORACLE_TIMESTAMP_TOLERANCE,
+                _ => return Err(Error::OracleError)
+            },
+            max_age => max_age as u64
         };
-        
-        let asset_price_data = reflector_contract.lastprice(&oracle_asset).unwrap_or_else(|| panic_with_error!(&env,Error::OracleError));
-        
-        // checking if the price is not stale (5 minutes sampling + 10 sec)
-        if (ledger.timestamp() - 310) > asset_price_data.timestamp {
-            panic_with_error!(&env, Error::OracleError);
+
+        if ledger.timestamp().saturating_sub(max_age) > asset_price_data.timestamp {
+            return Err(Error::OraclePriceStale);
         }
 
-        asset_price_data.price
+        Ok(asset_price_data)
     }
 }
 
@@ -127,13 +685,15 @@ impl OracleAsset {
 pub enum LoanStatus {
     WaitingForLender,
     WaitingForBorrower,
-    InProgress
+    InProgress,
+    Auction
 }
 
 
 #[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(u32)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     NotAuthorized = 0,
 
@@ -147,6 +707,17 @@ pub enum Error {
     LendingError = 107,
     BorrowingError = 108,
     CollateralNotSeizable = 109,
+    InvalidAmount = 110,
+    AmendmentNotExist = 111,
+    AmendmentAlreadyExist = 112,
+    PositionNotTokenized = 113,
+    CollateralNotWithdrawable = 114,
+    LiquidationDisabled = 115,
+    AuctionNotExist = 116,
+    FloatingRateDisabled = 117,
+    InstallmentsDisabled = 118,
+    InvalidLoanKey = 119,
 
-    OracleError = 500
+    OracleError = 500,
+    OraclePriceStale = 501
 }
\ No newline at end of file
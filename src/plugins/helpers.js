@@ -34,11 +34,15 @@ export default {
                 let seconds = Math.floor(Date.now() / 1000) - Number(loan.timestamp);
                 let days = Math.ceil(seconds / 86_400);
 
-                if(days > loan.max_loan_term) return 'Default';
+                if(days > loan.max_loan_term + (loan.grace_period || 0)) return 'Default';
+
+                if(days > loan.max_loan_term) return `Overdue (${days} days)`;
 
                 return `In Progress (${days} days)`;
             }
 
+            if(loan.status[0] == 'Auction') return 'Collateral Auction';
+
             return 'N/A';
         }
         